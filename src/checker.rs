@@ -1,25 +1,125 @@
 use std::io::{self, BufWriter};
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::Path;
 
-pub fn check_input_errors(old: &PathBuf, new: &PathBuf, errors: &mut u32) {
-    let stdout = io::stdout();
-    let mut buff = BufWriter::new(stdout);
+use crate::conflict;
+use crate::output;
+use crate::plan::{self, RenamePlan};
+use crate::policy::RunOpts;
+use crate::portable::{self, FoldedNames};
+use crate::transfer::Mode;
 
-    if old.is_file() && !new.is_file() {
-        write!(buff, "[ OK ]\t\t").unwrap();
-    } else if !old.is_file() && !new.is_file() {
-        write!(buff, "\x1b[0;41m[Error 1]\x1b[0m\t").unwrap();
-        *errors += 1;
-    } else if !old.is_file() && new.is_file() {
-        write!(buff, "\x1b[0;41m[Error 2]\x1b[0m\t").unwrap();
-        *errors += 1;
+// Kinds of errors found by the input check, numbered from 1.
+const ERROR_KINDS: &[&str] = &[
+    "The original file is not found",
+    "The original file is not found, a file exists for the proposed name.",
+    "The original file is found, a file exists for the proposed name.",
+    "A file exists in place of the destination directory.",
+    "The proposed name is not portable to macOS or Windows.",
+];
+
+// The check of a row, kept for the report.
+// Status is ok, new-dir, chain, or error. Error is the kind of error.
+// Note explains chains and names that are not portable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checked {
+    pub status: &'static str,
+    pub error: Option<usize>,
+    pub note: Option<String>,
+}
+
+// A proposed name that belongs to another file in the batch is not
+// a conflict when renaming. That file is renamed first,
+// or swapped through a temporary name. Copies and links keep
+// the original files, so their names stay taken.
+// Missing destination directories are created when renaming.
+// Chains, where a proposed name is the original name in another row,
+// are reported with the line of that row.
+// Portable checks the new names against macOS and Windows rules,
+// given the folded names of the batch.
+pub fn check_input_errors(
+    old: &Path,
+    new: &Path,
+    plan: &RenamePlan,
+    run: &RunOpts,
+    portable: Option<&FoldedNames>,
+    errors: &mut u32
+) -> Checked {
+    let chained = plan.origin_line(new).filter(|_| plan::get_key(old) != plan::get_key(new));
+    let moved_away = run.mode == Mode::Rename && chained.is_some();
+    let taken = conflict::is_taken(new) && !moved_away;
+    let dir = new.parent().unwrap_or_else(|| Path::new(""));
+    let not_portable = portable.and_then(|names| portable::check_portable(old, new, plan, names, run.mode));
+
+    let (status, error) = if !is_valid_dir(dir) {
+        ("error", Some(4))
+    } else if not_portable.is_some() {
+        ("error", Some(5))
+    } else if old.is_file() && !taken && !is_current_dir(dir) && !dir.exists() {
+        ("new-dir", None)
+    } else if old.is_file() && !taken && moved_away {
+        ("chain", None)
+    } else if old.is_file() && !taken {
+        ("ok", None)
+    } else if !old.is_file() && !taken {
+        ("error", Some(1))
+    } else if !old.is_file() && taken {
+        ("error", Some(2))
     } else {
-        write!(buff, "\x1b[0;41m[Error 3]\x1b[0m\t").unwrap();
+        ("error", Some(3))
+    };
+
+    let chain_note = chained.map(|line| {
+        let note = match moved_away {
+            true => "RENAMED FIRST",
+            false => "KEPT IN PLACE",
+        };
+        format!("{:?} IS THE ORIGINAL NAME IN LINE {}. {}.", new, line, note)
+    });
+    let note = not_portable.or(chain_note);
+
+    if error.is_some() {
         *errors += 1;
-    } 
+    }
 
-    writeln!(buff, "{:?} \x1b[0;36m => \x1b[0m {:?}", old, new).unwrap();
+    if error.is_some() || output::show_files(plan.len()) {
+        display_check(old, new, status, error, &note);
+    }
+
+    Checked { status, error, note }
+}
+
+// Errors are shown at every level. Other rows only when each file gets a line.
+fn display_check(old: &Path, new: &Path, status: &str, error: Option<usize>, note: &Option<String>) {
+    let stdout = io::stdout();
+    let mut buff = BufWriter::new(stdout);
+    match (status, error) {
+        (_, Some(kind)) => write!(buff, "{}\t", output::red(format!("[Error {}]", kind))).unwrap(),
+        ("new-dir", _) => write!(buff, "[New dir]\t").unwrap(),
+        ("chain", _) => write!(buff, "[ Chain ]\t").unwrap(),
+        _ => write!(buff, "[ OK ]\t\t").unwrap(),
+    }
+
+    writeln!(buff, "{:?} {} {:?}", old, output::cyan(" => "), new).unwrap();
+    if let Some(note) = note {
+        writeln!(buff, "\t\t{}", output::yellow(note)).unwrap();
+    }
+}
+
+pub fn describe_error(kind: usize) -> &'static str {
+    ERROR_KINDS.get(kind - 1).copied().unwrap_or_default()
+}
+
+// The directory is valid if it exists,
+// or if it can be created under an existing directory.
+fn is_valid_dir(dir: &Path) -> bool {
+    dir.ancestors()
+        .find(|d| is_current_dir(d) || d.exists())
+        .is_none_or(|d| is_current_dir(d) || d.is_dir())
+}
+
+fn is_current_dir(dir: &Path) -> bool {
+    dir.as_os_str().is_empty()
 }
 
 pub fn display_errors(counts: &u32) {
@@ -27,23 +127,92 @@ pub fn display_errors(counts: &u32) {
     let mut buff = BufWriter::new(stdout);
     writeln!(buff, "Errors found: {}", counts).unwrap();
     writeln!(buff, "\nError Kinds:").unwrap();
-    writeln!(buff, "Error 1: The original file is not found").unwrap();
-    writeln!(buff, "Error 2: The original file is not found, a file exists for the proposed name.").unwrap();
-    writeln!(buff, "Error 3: The original file is found, a file exists for the proposed name.").unwrap();
+    ERROR_KINDS.iter()
+        .enumerate()
+        .for_each(|(i, kind)| writeln!(buff, "Error {}: {}", i + 1, kind).unwrap());
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::path::PathBuf;
 
     #[test]
     fn check_input_errors_test() {
         let old = PathBuf::from("test_files/valid.fastq.gz");
         let new = PathBuf::from("test_files/valid_new.fastq.gz");
         let mut errors = 0;
-        check_input_errors(&old, &new, &mut errors);
+        let plan = RenamePlan::new();
+        check_input_errors(&old, &new, &plan, &RunOpts::default(), None, &mut errors);
+
+        assert_eq!(0, errors);
+    }
+
+    #[test]
+    fn check_swap_names_test() {
+        let old = PathBuf::from("test_files/valid.fastq.gz");
+        let new = PathBuf::from("test_files/valid2.fastq.gzip");
+        let mut plan = RenamePlan::new();
+        plan.push(2, old.clone(), new.clone()).unwrap();
+
+        let mut errors = 0;
+        check_input_errors(&old, &new, &plan, &RunOpts::default(), None, &mut errors);
+        assert_eq!(1, errors);
+
+        plan.push(3, new.clone(), old.clone()).unwrap();
+        check_input_errors(&old, &new, &plan, &RunOpts::default(), None, &mut errors);
+        check_input_errors(&new, &old, &plan, &RunOpts::default(), None, &mut errors);
+        assert_eq!(1, errors);
+
+        // Copies can't swap names. Both names stay taken.
+        let copy = RunOpts { mode: Mode::Copy, ..RunOpts::default() };
+        check_input_errors(&old, &new, &plan, &copy, None, &mut errors);
+        check_input_errors(&new, &old, &plan, &copy, None, &mut errors);
+        assert_eq!(3, errors);
+    }
+
+    #[test]
+    fn check_destination_dir_test() {
+        let old = PathBuf::from("test_files/valid.fastq.gz");
+        let plan = RenamePlan::new();
+        let mut errors = 0;
 
+        let new = PathBuf::from("test_files/project_a/run_1/valid.fastq.gz");
+        check_input_errors(&old, &new, &plan, &RunOpts::default(), None, &mut errors);
         assert_eq!(0, errors);
+
+        let new = PathBuf::from("test_files/valid2.fq.gz/valid.fastq.gz");
+        check_input_errors(&old, &new, &plan, &RunOpts::default(), None, &mut errors);
+        assert_eq!(1, errors);
+    }
+
+    #[test]
+    fn check_portable_names_test() {
+        let old = PathBuf::from("test_files/valid.fastq.gz");
+        let mut plan = RenamePlan::new();
+        plan.push(2, old.clone(), PathBuf::from("test_files/nul.fastq.gz")).unwrap();
+        let mut errors = 0;
+
+        let new = PathBuf::from("test_files/nul.fastq.gz");
+        check_input_errors(&old, &new, &plan, &RunOpts::default(), None, &mut errors);
+        assert_eq!(0, errors);
+
+        let portable = RunOpts { portable: true, ..RunOpts::default() };
+        let names = FoldedNames::new(&plan);
+        check_input_errors(&old, &new, &plan, &portable, Some(&names), &mut errors);
+        assert_eq!(1, errors);
+
+        let new = PathBuf::from("test_files/Valid2.FQ.GZ");
+        check_input_errors(&old, &new, &plan, &portable, Some(&names), &mut errors);
+        assert_eq!(2, errors);
+    }
+
+    #[test]
+    fn is_valid_dir_test() {
+        assert!(is_valid_dir(Path::new("")));
+        assert!(is_valid_dir(Path::new("test_files")));
+        assert!(is_valid_dir(Path::new("test_files/new/dir")));
+        assert!(!is_valid_dir(Path::new("test_files/valid.fastq.gz/dir")));
     }
 
 }
\ No newline at end of file
@@ -0,0 +1,423 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, ErrorKind, LineWriter, Write};
+use std::path::{Component, Path, PathBuf};
+
+use md5::Md5;
+use sha2::Digest;
+
+use crate::error::Error;
+use crate::journal::Journal;
+use crate::output::{self, Progress};
+use crate::plan::RenamePlan;
+use crate::planner::Step;
+use crate::renamer::Outcome;
+use crate::transfer;
+
+const MANIFEST_PREFIX: &str = "renamer-manifest_";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Algorithm {
+    Md5,
+    Sha256,
+    Blake3,
+}
+
+impl Algorithm {
+    pub fn parse(algo: &str) -> Result<Self, String> {
+        match algo {
+            "md5" => Ok(Algorithm::Md5),
+            "sha256" => Ok(Algorithm::Sha256),
+            "blake3" => Ok(Algorithm::Blake3),
+            a => Err(format!("Invalid checksum: {}. Use md5, sha256, or blake3.", a)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::Md5 => "md5",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Blake3 => "blake3",
+        }
+    }
+
+    // The manifest extension tells the algorithm, as in sample.md5.
+    // Manifests of b3sum often end with .b3.
+    fn from_manifest(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_string_lossy().to_lowercase();
+        match ext.as_str() {
+            "b3" => Some(Algorithm::Blake3),
+            ext => Algorithm::parse(ext).ok(),
+        }
+    }
+}
+
+// Sha256 is shared with the checksums of moves across filesystems.
+pub fn compute_checksum(path: &Path, algo: Algorithm) -> io::Result<String> {
+    let checksum = match algo {
+        Algorithm::Md5 => {
+            let mut hasher = Md5::new();
+            io::copy(&mut File::open(path)?, &mut hasher)?;
+            format!("{:x}", hasher.finalize())
+        }
+        Algorithm::Sha256 => transfer::compute_checksum(path)?.1,
+        Algorithm::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            io::copy(&mut File::open(path)?, &mut hasher)?;
+            hasher.finalize().to_hex().to_string()
+        }
+    };
+
+    Ok(checksum)
+}
+
+// Checksums of the original files before renaming,
+// keyed by the proposed name of each row.
+// Missing files are left out. The renaming reports them.
+pub fn compute_plan_checksums(plan: &RenamePlan, algo: Algorithm) -> Result<Vec<(PathBuf, String)>, Error> {
+    info!("Computing {} checksums...", algo.as_str());
+    let mut progress = Progress::new(plan.len());
+    let mut checksums = Vec::new();
+    for e in plan.entries().iter() {
+        progress.tick();
+        if !e.origin.is_file() {
+            continue;
+        }
+        let checksum = compute_checksum(&e.origin, algo).map_err(Error::io(&e.origin))?;
+        checksums.push((e.destination.to_path_buf(), checksum));
+    }
+    progress.finish();
+
+    Ok(checksums)
+}
+
+// Same as the plan checksums, for the steps left in an interrupted run.
+// A file under a temporary name keeps its checksum to the final name.
+pub fn compute_step_checksums(steps: &[Step], algo: Algorithm) -> Result<Vec<(PathBuf, String)>, Error> {
+    info!("Computing {} checksums...", algo.as_str());
+    let mut progress = Progress::new(steps.len());
+    let mut held: HashMap<&Path, String> = HashMap::new();
+    let mut checksums = Vec::new();
+    for step in steps {
+        progress.tick();
+        let checksum = match held.remove(step.origin.as_path()) {
+            Some(checksum) => checksum,
+            None if step.origin.is_file() => {
+                compute_checksum(&step.origin, algo).map_err(Error::io(&step.origin))?
+            }
+            None => continue,
+        };
+        match step.temporary {
+            true => { held.insert(&step.destination, checksum); }
+            false => checksums.push((step.destination.to_path_buf(), checksum)),
+        }
+    }
+    progress.finish();
+
+    Ok(checksums)
+}
+
+// Every file that got a new name is checked against its checksum
+// before renaming. Matching files are written to the manifest
+// under their new names, next to the journal.
+// The names are relative to the manifest, so it can be checked from anywhere.
+pub fn check_renamed_files(
+    before: &[(PathBuf, String)],
+    outcomes: &[Outcome],
+    journal: &Journal,
+    algo: Algorithm
+) -> Result<(), Error> {
+    info!("Verifying {} checksums...", algo.as_str());
+    let before: HashMap<&Path, &String> = before.iter()
+        .map(|(dest, checksum)| (dest.as_path(), checksum))
+        .collect();
+    let manifest = get_manifest_names(journal.path(), algo);
+    let manifest_dir = get_absolute_dir(&manifest).map_err(Error::io(&manifest))?;
+    let mut verified = Vec::new();
+    let mut mismatches = Vec::new();
+    for outcome in outcomes.iter().filter(|o| o.status.is_done()) {
+        let expected = before.get(outcome.destination.as_path());
+        let checksum = compute_checksum(&outcome.new_names, algo)
+            .map_err(Error::io(&outcome.new_names))?;
+        match expected {
+            Some(expected) if **expected == checksum => {
+                let names = get_absolute_dir(&outcome.new_names)
+                    .map_err(Error::io(&outcome.new_names))?
+                    .join(outcome.new_names.file_name().unwrap_or_default());
+                verified.push((get_relative_path(&names, &manifest_dir), checksum));
+            }
+            _ => {
+                warn!("{:?} {}", outcome.new_names, output::red("CHECKSUM MISMATCH!"));
+                mismatches.push(outcome.new_names.to_path_buf());
+            }
+        }
+    }
+
+    write_manifest(&manifest, &verified).map_err(Error::io(&manifest))?;
+    info!("Checksums verified: {}", verified.len());
+    info!("The manifest is saved as {}", manifest.display());
+
+    if !mismatches.is_empty() {
+        return Err(Error::Mismatch(mismatches));
+    }
+
+    Ok(())
+}
+
+pub fn is_manifest(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|f| f.to_string_lossy().starts_with(MANIFEST_PREFIX))
+}
+
+// Shares the time stamp of the journal, such as
+// renamer-manifest_20210116_120000.md5 for renamer-journal_20210116_120000.tsv.
+fn get_manifest_names(journal: &Path, algo: Algorithm) -> PathBuf {
+    let stem = journal.file_stem().unwrap_or_default().to_string_lossy();
+    let stamp = stem.split_once('_').map_or("", |(_, stamp)| stamp);
+    journal.with_file_name(format!("{}{}.{}", MANIFEST_PREFIX, stamp, algo.as_str()))
+}
+
+// The directory of the file with symlinks resolved.
+// The file itself is not resolved, so symlinks keep their own names.
+fn get_absolute_dir(path: &Path) -> io::Result<PathBuf> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => fs::canonicalize(dir),
+        _ => fs::canonicalize("."),
+    }
+}
+
+// Both paths are absolute. Paths without a common root stay absolute.
+fn get_relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    let common = path.iter()
+        .zip(base.iter())
+        .take_while(|(p, b)| p == b)
+        .count();
+    if common == 0 {
+        return path.iter().collect();
+    }
+
+    base[common..].iter()
+        .map(|_| Component::ParentDir)
+        .chain(path[common..].iter().copied())
+        .collect()
+}
+
+// Same format as md5sum, sha256sum, and b3sum: the checksum,
+// two spaces, and the path. Paths with a backslash or a line break
+// are escaped, and their line starts with a backslash.
+fn write_manifest(path: &Path, checksums: &[(PathBuf, String)]) -> io::Result<()> {
+    let file = File::create(path)?;
+    let mut line = LineWriter::new(file);
+    for (file_path, checksum) in checksums {
+        let names = file_path.to_string_lossy();
+        if names.contains(['\\', '\n', '\r']) {
+            let escaped = names.replace('\\', "\\\\")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r");
+            writeln!(line, "\\{}  {}", checksum, escaped)?;
+        } else {
+            writeln!(line, "{}  {}", checksum, names)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Reads manifests of md5sum and the like, in text or binary mode.
+// Blank lines and comments are skipped.
+fn read_manifest(path: &Path) -> Result<Vec<(PathBuf, String)>, Error> {
+    let file = File::open(path).map_err(Error::io(path))?;
+    let mut checksums = Vec::new();
+    for (idx, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(Error::io(path))?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid_line = || Error::InvalidInput {
+            path: path.to_path_buf(),
+            msg: format!("CAN'T READ LINE {} OF THE MANIFEST.", idx + 1),
+        };
+        let (escaped, line) = match line.strip_prefix('\\') {
+            Some(line) => (true, line),
+            None => (false, line.as_str()),
+        };
+        let (checksum, names) = line.split_once("  ")
+            .or_else(|| line.split_once(" *"))
+            .ok_or_else(invalid_line)?;
+        if checksum.is_empty() || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid_line());
+        }
+
+        let names = match escaped {
+            true => unescape(names),
+            false => names.to_string(),
+        };
+        checksums.push((PathBuf::from(names), checksum.to_lowercase()));
+    }
+
+    Ok(checksums)
+}
+
+fn unescape(names: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = names.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('\\')) => {
+                chars.next();
+                unescaped.push('\\');
+            }
+            ('\\', Some('n')) => {
+                chars.next();
+                unescaped.push('\n');
+            }
+            ('\\', Some('r')) => {
+                chars.next();
+                unescaped.push('\r');
+            }
+            (c, _) => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+// Checks the files in a manifest against their checksums.
+// Paths are relative to the directory of the manifest.
+// Without an algorithm, it follows the manifest extension.
+pub fn verify_files(manifest: &str, algo: Option<Algorithm>) -> Result<(), Error> {
+    let path = Path::new(manifest);
+    let algo = algo.or_else(|| Algorithm::from_manifest(path))
+        .ok_or_else(|| Error::InvalidInput {
+            path: path.to_path_buf(),
+            msg: String::from("UNKNOWN CHECKSUM. USE --checksum md5, sha256, or blake3."),
+        })?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let checksums: Vec<(PathBuf, String)> = read_manifest(path)?
+        .into_iter()
+        .map(|(file_path, checksum)| (dir.join(file_path), checksum))
+        .collect();
+
+    info!("Verifying {} checksums...", algo.as_str());
+    let show = output::show_files(checksums.len());
+    let mut progress = Progress::new(checksums.len());
+    let mut failed = Vec::new();
+    for (file_path, expected) in checksums.iter() {
+        progress.tick();
+        match compute_checksum(file_path, algo) {
+            Ok(checksum) if checksum == *expected => {
+                if show {
+                    info!("{:?} {}", file_path, output::cyan("OK"));
+                }
+            }
+            Ok(_) => {
+                warn!("{:?} {}", file_path, output::red("FAILED!"));
+                failed.push(file_path.to_path_buf());
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                warn!("{:?} {}", file_path, output::red("NOT FOUND!"));
+                failed.push(file_path.to_path_buf());
+            }
+            Err(e) => {
+                warn!("{:?} {} {}", file_path, output::red("CAN'T READ!"), e);
+                failed.push(file_path.to_path_buf());
+            }
+        }
+    }
+    progress.finish();
+
+    info!("\nFiles verified: {}", checksums.len() - failed.len());
+    if !failed.is_empty() {
+        return Err(Error::Mismatch(failed));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn compute_checksum_test() {
+        let dir = env::temp_dir().join("renamer_checksum_algo_test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("abc.txt");
+        fs::write(&path, "abc").unwrap();
+
+        assert_eq!("900150983cd24fb0d6963f7d28e17f72", compute_checksum(&path, Algorithm::Md5).unwrap());
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            compute_checksum(&path, Algorithm::Sha256).unwrap()
+        );
+        assert_eq!(
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+            compute_checksum(&path, Algorithm::Blake3).unwrap()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn manifest_test() {
+        let dir = env::temp_dir().join("renamer_manifest_test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("renamer-manifest_001.md5");
+        let checksums = vec![
+            (PathBuf::from("raw/a b.fq"), String::from("900150983cd24fb0d6963f7d28e17f72")),
+            (PathBuf::from("raw/a\\b.fq"), String::from("d41d8cd98f00b204e9800998ecf8427e")),
+            (PathBuf::from("raw/a\r\nb.fq"), String::from("d41d8cd98f00b204e9800998ecf8427e")),
+        ];
+        write_manifest(&path, &checksums).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(contents.starts_with("900150983cd24fb0d6963f7d28e17f72  raw/a b.fq\n"));
+        assert!(contents.contains("\\d41d8cd98f00b204e9800998ecf8427e  raw/a\\\\b.fq\n"));
+        assert!(contents.contains("\\d41d8cd98f00b204e9800998ecf8427e  raw/a\\r\\nb.fq\n"));
+        assert_eq!(checksums, read_manifest(&path).unwrap());
+
+        fs::write(&path, "not a manifest\n").unwrap();
+        assert!(read_manifest(&path).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn get_relative_path_test() {
+        let base = Path::new("/data/run");
+        assert_eq!(PathBuf::from("raw/a.fq"), get_relative_path(Path::new("/data/run/raw/a.fq"), base));
+        assert_eq!(PathBuf::from("../other/a.fq"), get_relative_path(Path::new("/data/other/a.fq"), base));
+    }
+
+    // Paths in the manifest follow the manifest, not the working directory.
+    #[test]
+    fn verify_files_test() {
+        let dir = env::temp_dir().join("renamer_verify_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(dir.join("raw")).unwrap();
+        fs::write(dir.join("raw/abc.txt"), "abc").unwrap();
+        let manifest = dir.join("renamer-manifest_001.md5");
+        fs::write(&manifest, "900150983cd24fb0d6963f7d28e17f72  raw/abc.txt\n").unwrap();
+
+        assert!(verify_files(&manifest.to_string_lossy(), None).is_ok());
+        fs::write(dir.join("raw/abc.txt"), "abcd").unwrap();
+        assert!(matches!(verify_files(&manifest.to_string_lossy(), None), Err(Error::Mismatch(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn algorithm_from_manifest_test() {
+        assert_eq!(Some(Algorithm::Md5), Algorithm::from_manifest(Path::new("run.md5")));
+        assert_eq!(Some(Algorithm::Blake3), Algorithm::from_manifest(Path::new("run.b3")));
+        assert_eq!(None, Algorithm::from_manifest(Path::new("run.txt")));
+    }
+
+    #[test]
+    fn get_manifest_names_test() {
+        let res = get_manifest_names(Path::new("data/renamer-journal_20210116_120000.tsv"), Algorithm::Sha256);
+        assert_eq!(PathBuf::from("data/renamer-manifest_20210116_120000.sha256"), res);
+    }
+}
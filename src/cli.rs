@@ -1,16 +1,86 @@
-use clap::{App, AppSettings, Arg};
+use std::path::{Path, PathBuf};
 
+use clap::{App, AppSettings, Arg, ArgMatches};
+use regex::Regex;
+
+use crate::checksum::{self, Algorithm};
+use crate::companion::{self, Rule};
+use crate::conflict::OnConflict;
+use crate::error::Error;
+use crate::extension;
 use crate::finder;
-use crate::parser;
+use crate::output::{self, ColorChoice, Level};
+use crate::parser::{self, InputOpts};
+use crate::policy::{self, OnError, RunOpts};
+use crate::recover;
+use crate::replacer::{self, RegexOpts};
+use crate::report;
+use crate::sanitizer::{self, Case, Rules, SanitizeOpts};
+use crate::transfer::Mode;
 use crate::renamer;
+use crate::template::Template;
 
 
-pub fn get_cli(version: &str) {
+pub fn get_cli(version: &str) -> Result<(), Error> {
     let args = App::new("renamer")
         .version(version)
         .about("Automates file renaming across directories")
         .author("Heru Handika <hhandi1@lsu.edu>")
         .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("compound-ext")
+                .long("compound-ext")
+                .help("Adds extensions made of more than one part, such as fastq.lz4. Comma separated.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .use_delimiter(true)
+                .global(true)
+                .env("RENAMER_EXTENSIONS")
+                .validator(|e| extension::parse_compound_ext(&e).map(|_| ()))
+                .value_name("EXTENSION")
+        )
+        .arg(
+            Arg::with_name("companion")
+                .long("companion")
+                .help("Adds rules for companion files, used with --companions. \
+                    A suffix, such as .bai, or EXT=SUFFIX, such as bam=.bai. Comma separated.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .use_delimiter(true)
+                .global(true)
+                .env("RENAMER_COMPANIONS")
+                .validator(|r| Rule::parse(&r).map(|_| ()))
+                .value_name("RULE")
+        )
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .help("Colors the output. Auto colors terminals unless NO_COLOR is set.")
+                .takes_value(true)
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .global(true)
+                .value_name("WHEN")
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .long("quiet")
+                .help("Only prints errors and warnings.")
+                .takes_value(false)
+                .conflicts_with("verbose")
+                .global(true)
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .help("Prints every file, even in large batches.")
+                .takes_value(false)
+                .global(true)
+        )
         .subcommand(
             App::new("find")
                 .about("Finds relevant fastq files")
@@ -49,6 +119,15 @@ pub fn get_cli(version: &str) {
                         .help("BPA database file format.")
                         .takes_value(false)
                 )
+
+                .arg(
+                    Arg::with_name("checksum")
+                        .long("checksum")
+                        .help("Adds a checksum column to the result.")
+                        .takes_value(true)
+                        .possible_values(&["md5", "sha256", "blake3"])
+                        .value_name("ALGORITHM")
+                )
         )
 
         .subcommand(
@@ -58,20 +137,346 @@ pub fn get_cli(version: &str) {
                     Arg::with_name("input")
                         .short("i")
                         .long("input")
-                        .help("Input file.")
+                        .help("Input file. Csv, tsv, xlsx, or ods.")
                         .takes_value(true)
                         .value_name("INPUT_FILE")
                 )
 
                 .arg(
-                    Arg::with_name("dry-run")
-                        .long("dry")
-                        .help("Dry run. Checks input first.")
+                    Arg::with_name("from")
+                        .long("from")
+                        .help("Column for the original names. Header name or number.")
+                        .takes_value(true)
+                        .value_name("COLUMN")
+                )
+
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .help("Column for the new names. Header name or number.")
+                        .takes_value(true)
+                        .value_name("COLUMN")
+                )
+
+                .arg(
+                    Arg::with_name("template")
+                        .long("template")
+                        .help("Builds the new names from the columns, such as \"{species}_{voucher}.{ext}\". \
+                            Built-ins: {stem}, {ext}, {row}, {row:N}, and {parent}.")
+                        .takes_value(true)
+                        .conflicts_with("to")
+                        .validator(|t| Template::parse(&t).map(|_| ()))
+                        .value_name("TEMPLATE")
+                )
+
+                .arg(
+                    Arg::with_name("delimiter")
+                        .long("delimiter")
+                        .help("Input delimiter, such as ',', ';', or tab. Detected if not set.")
+                        .takes_value(true)
+                        .validator(|d| parser::parse_delimiter(&d).map(|_| ()))
+                        .value_name("DELIMITER")
+                )
+
+                .arg(
+                    Arg::with_name("sheet")
+                        .long("sheet")
+                        .help("Sheet name for xlsx or ods input. Uses the first sheet if not set.")
+                        .takes_value(true)
+                        .value_name("SHEET")
+                )
+
+                .arg(
+                    Arg::with_name("header-row")
+                        .long("header-row")
+                        .help("Header row number for xlsx or ods input.")
+                        .takes_value(true)
+                        .validator(|r| match r.parse::<usize>() {
+                            Ok(r) if r > 0 => Ok(()),
+                            _ => Err(String::from("Header row must be a number from 1.")),
+                        })
+                        .value_name("ROW")
+                )
+
+                .arg(mode_arg())
+
+                .arg(on_conflict_arg())
+
+                .arg(
+                    Arg::with_name("move")
+                        .long("move")
+                        .help("Treats the new names as full paths relative to the input file, \
+                            so files can be moved to other directories.")
                         .takes_value(false)
                 )
+
+                .arg(
+                    Arg::with_name("dest-root")
+                        .long("dest-root")
+                        .help("Directory the new names are relative to in move mode.")
+                        .requires("move")
+                        .takes_value(true)
+                        .value_name("DIR")
+                )
+
+                .arg(portable_arg())
+
+                .arg(companions_arg())
+
+                .arg(dry_run_arg())
+
+                .arg(report_arg())
+
+                .arg(
+                    Arg::with_name("checksum")
+                        .long("checksum")
+                        .help("Verifies the files before and after renaming, \
+                            and writes a manifest of the new names next to the journal.")
+                        .takes_value(true)
+                        .possible_values(&["md5", "sha256", "blake3"])
+                        .value_name("ALGORITHM")
+                )
+
+                .arg(yes_arg())
+
+                .arg(on_error_arg())
+        )
+
+        .subcommand(
+            App::new("regex")
+            .about("Renames files matching a regular expression, without an input file.")
+                .arg(
+                    Arg::with_name("dir")
+                        .short("d")
+                        .long("dir")
+                        .help("Inputs directory to traverse.")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("DIR")
+                )
+
+                .arg(
+                    Arg::with_name("pattern")
+                        .short("p")
+                        .long("pattern")
+                        .help("Regular expression matched against the file names without their extension.")
+                        .required(true)
+                        .takes_value(true)
+                        .validator(|p| Regex::new(&p).map(|_| ()).map_err(|e| e.to_string()))
+                        .value_name("REGEX")
+                )
+
+                .arg(
+                    Arg::with_name("replace")
+                        .short("r")
+                        .long("replace")
+                        .help("Replacement for the matches. Use $1 or ${name} for capture groups.")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("TEMPLATE")
+                )
+
+                .arg(
+                    Arg::with_name("specify")
+                        .short("s")
+                        .long("specify")
+                        .help("Only renames files with this extension. Fastq finds compressed fastq files.")
+                        .takes_value(true)
+                        .value_name("EXTENSION")
+                )
+
+                .arg(mode_arg())
+
+                .arg(on_conflict_arg())
+
+                .arg(portable_arg())
+
+                .arg(companions_arg())
+
+                .arg(dry_run_arg())
+
+                .arg(report_arg())
+
+                .arg(yes_arg())
+
+                .arg(on_error_arg())
+        )
+
+        .subcommand(
+            App::new("sanitize")
+            .about("Proposes safe file names for shell tools. Writes them to a csv or applies them.")
+                .arg(
+                    Arg::with_name("dir")
+                        .short("d")
+                        .long("dir")
+                        .help("Inputs directory to traverse.")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("DIR")
+                )
+
+                .arg(
+                    Arg::with_name("specify")
+                        .short("s")
+                        .long("specify")
+                        .help("Only sanitizes files with this extension. Fastq finds compressed fastq files.")
+                        .takes_value(true)
+                        .value_name("EXTENSION")
+                )
+
+                .arg(
+                    Arg::with_name("allow")
+                        .long("allow")
+                        .help("Characters kept besides letters and digits.")
+                        .takes_value(true)
+                        .default_value("._-")
+                        .value_name("CHARS")
+                )
+
+                .arg(
+                    Arg::with_name("replacement")
+                        .long("replacement")
+                        .help("Character in place of the characters not allowed.")
+                        .takes_value(true)
+                        .default_value("_")
+                        .validator(|r| match r.chars().count() {
+                            1 => Ok(()),
+                            _ => Err(String::from("Use a single character.")),
+                        })
+                        .value_name("CHAR")
+                )
+
+                .arg(
+                    Arg::with_name("case")
+                        .long("case")
+                        .help("Folds the names to lower or upper case.")
+                        .takes_value(true)
+                        .possible_values(&["keep", "lower", "upper"])
+                        .default_value("keep")
+                        .value_name("CASE")
+                )
+
+                .arg(
+                    Arg::with_name("nfc")
+                        .long("nfc")
+                        .help("Keeps non-ASCII letters and digits, normalized to Unicode NFC.")
+                        .takes_value(false)
+                )
+
+                .arg(
+                    Arg::with_name("transliterate")
+                        .long("transliterate")
+                        .help("Turns non-ASCII characters into ASCII, such as é to e.")
+                        .conflicts_with("nfc")
+                        .takes_value(false)
+                )
+
+                .arg(
+                    Arg::with_name("collapse")
+                        .long("collapse")
+                        .help("Collapses repeated separators and trims them from both ends of the names.")
+                        .takes_value(false)
+                )
+
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .help("Csv for the proposed names. Use it as the rename input after review.")
+                        .takes_value(true)
+                        .default_value("renamer-sanitize.csv")
+                        .value_name("OUTPUT")
+                )
+
+                .arg(
+                    Arg::with_name("apply")
+                        .long("apply")
+                        .help("Renames the files directly instead of writing the csv.")
+                        .takes_value(false)
+                )
+
+                .arg(on_conflict_arg())
+
+                .arg(portable_arg())
+
+                .arg(companions_arg())
+
+                .arg(dry_run_arg().requires("apply"))
+
+                .arg(report_arg())
+
+                .arg(yes_arg())
+
+                .arg(on_error_arg())
+        )
+
+        .subcommand(
+            App::new("undo")
+            .about("Reverses a past run given its journal.")
+                .arg(
+                    Arg::with_name("journal")
+                        .help("Journal file.")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("JOURNAL")
+                )
+        )
+
+        .subcommand(
+            App::new("verify")
+            .about("Checks files against a checksum manifest.")
+                .arg(
+                    Arg::with_name("manifest")
+                        .help("Manifest file, as written by renamer, md5sum, sha256sum, or b3sum.")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("MANIFEST")
+                )
+
+                .arg(
+                    Arg::with_name("checksum")
+                        .long("checksum")
+                        .help("Checksum algorithm. Follows the manifest extension if not set.")
+                        .takes_value(true)
+                        .possible_values(&["md5", "sha256", "blake3"])
+                        .value_name("ALGORITHM")
+                )
+        )
+
+        .subcommand(
+            App::new("recover")
+            .about("Finishes or rolls back an interrupted run given its journal.")
+                .alias("resume")
+                .arg(
+                    Arg::with_name("journal")
+                        .help("Journal file.")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("JOURNAL")
+                )
+
+                .arg(
+                    Arg::with_name("rollback")
+                        .long("rollback")
+                        .help("Rolls back the interrupted run instead of finishing it.")
+                        .takes_value(false)
+                )
+
+                .arg(on_error_arg())
         )
         .get_matches();
 
+    output::init(ColorChoice::parse(args.value_of("color").unwrap()).unwrap(), get_level(&args));
+    if let Some(exts) = args.values_of("compound-ext") {
+        let exts: Vec<String> = exts.map(|e| extension::parse_compound_ext(e).unwrap()).collect();
+        extension::register_extensions(&exts);
+    }
+
+    if let Some(rules) = args.values_of("companion") {
+        let rules: Vec<Rule> = rules.map(|r| Rule::parse(r).unwrap()).collect();
+        companion::register_rules(&rules);
+    }
+
     match args.subcommand() {
 
         ("find", Some(find_matches)) => {
@@ -80,9 +485,9 @@ pub fn get_cli(version: &str) {
                 let ext = find_matches.value_of("specify").unwrap();
 
                 if find_matches.is_present("bpa") {
-                    finder::process_input_dir(path, ext, true)
+                    finder::process_input_dir(path, ext, true, get_checksum(find_matches))?;
                 } else {
-                    finder::process_input_dir(path, ext, false);
+                    finder::process_input_dir(path, ext, false, get_checksum(find_matches))?;
                 }      
 
             } else if find_matches.is_present("wildcard") {
@@ -92,30 +497,209 @@ pub fn get_cli(version: &str) {
                     .collect();
 
                 if find_matches.is_present("bpa") {
-                    finder::process_input_wcard(&entries, true);
+                    finder::process_input_wcard(&entries, true, get_checksum(find_matches))?;
                 } else {
-                    finder::process_input_wcard(&entries, false);
+                    finder::process_input_wcard(&entries, false, get_checksum(find_matches))?;
                 }
                 
             } else {
-                println!("NO COMMANDS PROVIDED!");
+                warn!("NO COMMANDS PROVIDED!");
             }
         }
         
         ("rename", Some(rename_matches)) => {
             if rename_matches.is_present("input") {
                 let input = rename_matches.value_of("input").unwrap();
+                let opts = InputOpts {
+                    from: rename_matches.value_of("from").map(String::from),
+                    to: rename_matches.value_of("to").map(String::from),
+                    delimiter: rename_matches.value_of("delimiter")
+                        .map(|d| parser::parse_delimiter(d).unwrap()),
+                    sheet: rename_matches.value_of("sheet").map(String::from),
+                    header_row: rename_matches.value_of("header-row")
+                        .map(|r| r.parse().unwrap()),
+                    dest_root: get_dest_root(rename_matches, input),
+                    template: rename_matches.value_of("template")
+                        .map(|t| Template::parse(t).unwrap()),
+                };
+
+                let run = get_run_opts(rename_matches);
 
-                if rename_matches.is_present("dry-run") {
-                    let dryrun = true;
-                    parser::parse_csv(input, dryrun);
+                if run.dryrun {
+                    parser::parse_input(input, &opts, &run)?;
 
                 } else {
-                    renamer::rename_files(input).unwrap();
+                    renamer::rename_files(input, &opts, &run)?;
                 }
             }
         }
         
+        ("regex", Some(regex_matches)) => {
+            let opts = RegexOpts {
+                dir: String::from(regex_matches.value_of("dir").unwrap()),
+                ext: regex_matches.value_of("specify").map(String::from),
+                pattern: Regex::new(regex_matches.value_of("pattern").unwrap()).unwrap(),
+                replace: String::from(regex_matches.value_of("replace").unwrap()),
+            };
+            replacer::rename_files(&opts, &get_run_opts(regex_matches))?;
+        }
+
+        ("sanitize", Some(sanitize_matches)) => {
+            let opts = SanitizeOpts {
+                dir: String::from(sanitize_matches.value_of("dir").unwrap()),
+                ext: sanitize_matches.value_of("specify").map(String::from),
+                output: String::from(sanitize_matches.value_of("output").unwrap()),
+                apply: sanitize_matches.is_present("apply"),
+                rules: Rules {
+                    allowed: String::from(sanitize_matches.value_of("allow").unwrap()),
+                    replacement: sanitize_matches.value_of("replacement").unwrap().chars().next().unwrap(),
+                    case: Case::parse(sanitize_matches.value_of("case").unwrap()).unwrap(),
+                    nfc: sanitize_matches.is_present("nfc"),
+                    transliterate: sanitize_matches.is_present("transliterate"),
+                    collapse: sanitize_matches.is_present("collapse"),
+                },
+            };
+            sanitizer::sanitize_files(&opts, &get_run_opts(sanitize_matches))?;
+        }
+
+        ("undo", Some(undo_matches)) => {
+            let journal = undo_matches.value_of("journal").unwrap();
+            renamer::undo_files(journal)?;
+        }
+
+        ("verify", Some(verify_matches)) => {
+            let manifest = verify_matches.value_of("manifest").unwrap();
+            checksum::verify_files(manifest, get_checksum(verify_matches))?;
+        }
+
+        ("recover", Some(recover_matches)) => {
+            let journal = recover_matches.value_of("journal").unwrap();
+            let rollback = recover_matches.is_present("rollback");
+            recover::recover_files(journal, rollback, get_on_error(recover_matches))?;
+        }
+
         _ => unreachable!("UNREACHABLE COMMANDS!"),
     };
+
+    Ok(())
+}
+
+// Arguments shared by the rename, regex, and sanitize commands.
+fn mode_arg() -> Arg<'static, 'static> {
+    Arg::with_name("mode")
+        .long("mode")
+        .help("How the files get their new names. \
+            Copy and links leave the original files untouched.")
+        .takes_value(true)
+        .possible_values(&["rename", "copy", "hardlink", "symlink"])
+        .default_value("rename")
+        .value_name("MODE")
+}
+
+fn on_conflict_arg() -> Arg<'static, 'static> {
+    Arg::with_name("on-conflict")
+        .long("on-conflict")
+        .help("What to do when a new name is taken by another file.")
+        .takes_value(true)
+        .possible_values(&["error", "skip", "suffix", "number", "overwrite", "backup"])
+        .default_value("suffix")
+        .value_name("STRATEGY")
+}
+
+fn portable_arg() -> Arg<'static, 'static> {
+    Arg::with_name("portable")
+        .long("portable")
+        .help("Checks the new names for macOS and Windows: case-insensitive collisions, \
+            reserved names, trailing dots and spaces, and illegal characters.")
+        .takes_value(false)
+}
+
+fn companions_arg() -> Arg<'static, 'static> {
+    Arg::with_name("companions")
+        .long("companions")
+        .help("Renames companion files, such as .bai and .md5, along with their files.")
+        .takes_value(false)
+}
+
+fn dry_run_arg() -> Arg<'static, 'static> {
+    Arg::with_name("dry-run")
+        .long("dry")
+        .help("Dry run. Checks input first.")
+        .takes_value(false)
+}
+
+fn report_arg() -> Arg<'static, 'static> {
+    Arg::with_name("report")
+        .long("report")
+        .help("Writes a report of the dry run or the run. Json or csv, following the file extension.")
+        .takes_value(true)
+        .validator(|r| report::Format::from_path(&r).map(|_| ()))
+        .value_name("REPORT")
+}
+
+fn yes_arg() -> Arg<'static, 'static> {
+    Arg::with_name("yes")
+        .long("yes")
+        .visible_alias("non-interactive")
+        .help("Never asks. Continues despite errors in the input check.")
+        .takes_value(false)
+}
+
+fn on_error_arg() -> Arg<'static, 'static> {
+    Arg::with_name("on-error")
+        .long("on-error")
+        .help("What to do when a file can't be renamed: skip, retry-N, or abort-rollback. Asks if not set.")
+        .takes_value(true)
+        .validator(|p| OnError::parse(&p).map(|_| ()))
+        .value_name("POLICY")
+}
+
+// In move mode, new names are relative to the input file
+// unless a destination root is given.
+fn get_dest_root(matches: &ArgMatches, input: &str) -> Option<PathBuf> {
+    if !matches.is_present("move") {
+        return None;
+    }
+
+    match matches.value_of("dest-root") {
+        Some(root) => Some(PathBuf::from(root)),
+        None => Some(Path::new(input).parent().unwrap_or_else(|| Path::new("")).to_path_buf()),
+    }
+}
+
+fn get_run_opts(matches: &ArgMatches) -> RunOpts {
+    RunOpts {
+        dryrun: matches.is_present("dry-run"),
+        yes: matches.is_present("yes"),
+        portable: matches.is_present("portable"),
+        companions: matches.is_present("companions"),
+        report: matches.value_of("report").map(PathBuf::from),
+        checksum: get_checksum(matches),
+        on_error: get_on_error(matches),
+        on_conflict: OnConflict::parse(matches.value_of("on-conflict").unwrap()).unwrap(),
+        mode: matches.value_of("mode").map_or(Mode::Rename, |m| Mode::parse(m).unwrap()),
+    }
+}
+
+fn get_checksum(matches: &ArgMatches) -> Option<Algorithm> {
+    matches.value_of("checksum").map(|c| Algorithm::parse(c).unwrap())
+}
+
+fn get_level(matches: &ArgMatches) -> Level {
+    if matches.is_present("quiet") {
+        Level::Quiet
+    } else if matches.is_present("verbose") {
+        Level::Verbose
+    } else {
+        Level::Normal
+    }
+}
+
+// Without a policy, we only ask when there is someone to answer.
+fn get_on_error(matches: &ArgMatches) -> OnError {
+    match matches.value_of("on-error") {
+        Some(policy) => OnError::parse(policy).unwrap(),
+        None if matches.is_present("yes") || !policy::is_interactive() => OnError::Skip,
+        None => OnError::Prompt,
+    }
 }
\ No newline at end of file
@@ -0,0 +1,183 @@
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::error::Error;
+use crate::plan::RenamePlan;
+
+// Files that belong to another file and follow its name,
+// such as indexes and checksums. Most add a suffix to the full name,
+// as sample.bam.bai. Some replace the extension, as sample.bai.
+const COMPANION_RULES: &[&str] = &[
+    ".bai", ".csi", ".crai", ".tbi", ".fai", ".md5", ".sha256",
+    "bam=.bai", "cram=.crai",
+];
+
+// Rules added by the user with --companion.
+static USER_RULES: RwLock<Vec<Rule>> = RwLock::new(Vec::new());
+
+// A suffix added to the file name.
+// With an extension, the suffix replaces it instead,
+// and the rule only applies to files with that extension.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    ext: Option<String>,
+    suffix: String,
+}
+
+impl Rule {
+    // Accepts SUFFIX or EXT=SUFFIX, with or without the leading dots,
+    // such as .bai or bam=.bai.
+    pub fn parse(rule: &str) -> Result<Self, String> {
+        let invalid_rule = || format!(
+            "Invalid companion rule: {}. Use a suffix, such as .bai, or EXT=SUFFIX, such as bam=.bai.", rule
+        );
+        let (ext, suffix) = match rule.split_once('=') {
+            Some((ext, suffix)) => (Some(ext.trim().trim_start_matches('.')), suffix),
+            None => (None, rule),
+        };
+        let suffix = suffix.trim().trim_start_matches('.');
+        if suffix.is_empty() || suffix.contains(['/', '\\']) || ext.is_some_and(|e| e.is_empty()) {
+            return Err(invalid_rule());
+        }
+
+        Ok(Self {
+            ext: ext.map(|e| format!(".{}", e.to_ascii_lowercase())),
+            suffix: format!(".{}", suffix),
+        })
+    }
+
+    // The name of the companion of the file, if the rule applies to it.
+    pub fn apply(&self, path: &Path) -> Option<PathBuf> {
+        let fname = path.file_name()?.to_string_lossy();
+        let base = match &self.ext {
+            Some(ext) => {
+                let lower = fname.to_ascii_lowercase();
+                if lower.len() <= ext.len() || !lower.ends_with(ext.as_str()) {
+                    return None;
+                }
+                &fname[..fname.len() - ext.len()]
+            }
+            None => &fname,
+        };
+
+        Some(path.with_file_name(format!("{}{}", base, self.suffix)))
+    }
+}
+
+pub fn register_rules(rules: &[Rule]) {
+    let mut registry = USER_RULES.write().expect("COMPANION REGISTRY IS POISONED");
+    for rule in rules {
+        if !registry.contains(rule) {
+            registry.push(rule.clone());
+        }
+    }
+}
+
+fn get_rules() -> Vec<Rule> {
+    let registry = USER_RULES.read().expect("COMPANION REGISTRY IS POISONED");
+    COMPANION_RULES.iter()
+        .map(|r| Rule::parse(r).expect("INVALID BUILT-IN COMPANION RULE"))
+        .chain(registry.iter().cloned())
+        .collect()
+}
+
+// The rule that names the companion after the file, if any.
+pub fn find_rule(file: &Path, companion: &Path) -> Option<Rule> {
+    get_rules().into_iter().find(|r| r.apply(file).as_deref() == Some(companion))
+}
+
+// Adds a row for each companion file found next to the files in the plan.
+// The companion gets the new name of its file, under the same line,
+// so it goes through the same checks and into the journal.
+// Companions already in the plan keep their own row.
+pub fn add_companions(plan: &mut RenamePlan) -> Result<(), Error> {
+    let entries = plan.entries().to_vec();
+    let rules = get_rules();
+    let mut problems = Vec::new();
+    let mut counts = 0;
+    for e in entries.iter() {
+        for rule in rules.iter() {
+            let companion = match rule.apply(&e.origin) {
+                Some(companion) if companion.is_file() && !plan.contains_origin(&companion) => companion,
+                _ => continue,
+            };
+
+            let new_names = match rule.apply(&e.destination) {
+                Some(new_names) => new_names,
+                None => {
+                    warn!("CAN'T NAME THE COMPANION {:?} AFTER {:?}. Skipping it...", companion, e.destination);
+                    continue;
+                }
+            };
+
+            match plan.push(e.line, companion, new_names) {
+                Ok(_) => counts += 1,
+                Err(err) => problems.push(err),
+            }
+        }
+    }
+
+    if counts > 0 {
+        info!("Companion files found: {}", counts);
+    }
+
+    if !problems.is_empty() {
+        return Err(Error::Table(problems));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn parse_rule_test() {
+        assert_eq!(Rule { ext: None, suffix: String::from(".bai") }, Rule::parse("bai").unwrap());
+        assert_eq!(
+            Rule { ext: Some(String::from(".bam")), suffix: String::from(".bai") },
+            Rule::parse(".BAM=.bai").unwrap()
+        );
+        assert!(Rule::parse("").is_err());
+        assert!(Rule::parse("=.bai").is_err());
+        assert!(Rule::parse("idx/.bai").is_err());
+    }
+
+    #[test]
+    fn apply_rule_test() {
+        let rule = Rule::parse(".tbi").unwrap();
+        assert_eq!(Some(PathBuf::from("data/a.vcf.gz.tbi")), rule.apply(Path::new("data/a.vcf.gz")));
+
+        let rule = Rule::parse("bam=.bai").unwrap();
+        assert_eq!(Some(PathBuf::from("data/a.bai")), rule.apply(Path::new("data/a.bam")));
+        assert_eq!(None, rule.apply(Path::new("data/a.cram")));
+    }
+
+    #[test]
+    fn add_companions_test() {
+        let dir = env::temp_dir().join("renamer_companion_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        ["a.bam", "a.bam.bai", "a.bai", "b.fastq.gz", "b.fastq.gz.md5", "c.vcf.gz", "c.vcf.gz.tbi"].iter()
+            .for_each(|f| { fs::File::create(dir.join(f)).unwrap(); });
+
+        let mut plan = RenamePlan::new();
+        plan.push(2, dir.join("a.bam"), dir.join("x.bam")).unwrap();
+        plan.push(3, dir.join("b.fastq.gz"), dir.join("y.fastq.gz")).unwrap();
+        plan.push(4, dir.join("c.vcf.gz.tbi"), dir.join("z.vcf.gz.tbi")).unwrap();
+        add_companions(&mut plan).unwrap();
+
+        let added: Vec<(usize, PathBuf, PathBuf)> = plan.entries()[3..].iter()
+            .map(|e| (e.line, e.origin.to_path_buf(), e.destination.to_path_buf()))
+            .collect();
+        assert_eq!(vec![
+            (2, dir.join("a.bam.bai"), dir.join("x.bam.bai")),
+            (2, dir.join("a.bai"), dir.join("x.bai")),
+            (3, dir.join("b.fastq.gz.md5"), dir.join("y.fastq.gz.md5")),
+        ], added);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
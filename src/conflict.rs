@@ -0,0 +1,175 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::extension::split_file_names;
+
+// What to do when the new name is already taken by a file
+// outside the batch.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OnConflict {
+    Error,
+    Skip,
+    #[default]
+    Suffix,
+    Number,
+    Overwrite,
+    Backup,
+}
+
+impl OnConflict {
+    pub fn parse(strategy: &str) -> Result<Self, String> {
+        match strategy {
+            "error" => Ok(OnConflict::Error),
+            "skip" => Ok(OnConflict::Skip),
+            "suffix" => Ok(OnConflict::Suffix),
+            "number" => Ok(OnConflict::Number),
+            "overwrite" => Ok(OnConflict::Overwrite),
+            "backup" => Ok(OnConflict::Backup),
+            s => Err(format!(
+                "Invalid conflict strategy: {}. \
+                Use error, skip, suffix, number, overwrite, or backup.", s
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OnConflict::Error => "error",
+            OnConflict::Skip => "skip",
+            OnConflict::Suffix => "suffix",
+            OnConflict::Number => "number",
+            OnConflict::Overwrite => "overwrite",
+            OnConflict::Backup => "backup",
+        }
+    }
+}
+
+// How a taken name is resolved.
+// Set aside holds the existing file while it is overwritten,
+// so it can be restored if the renaming fails.
+#[derive(Debug, PartialEq)]
+pub enum Resolution {
+    Free,
+    Error,
+    Skip,
+    Rename(PathBuf),
+    Overwrite { set_aside: PathBuf },
+    Backup(PathBuf),
+}
+
+pub fn is_taken(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
+
+// Fails only when every numbered name is taken.
+pub fn resolve_conflict(destination: &Path, on_conflict: OnConflict) -> io::Result<Resolution> {
+    if !is_taken(destination) {
+        return Ok(Resolution::Free);
+    }
+
+    let resolution = match on_conflict {
+        OnConflict::Error => Resolution::Error,
+        OnConflict::Skip => Resolution::Skip,
+        OnConflict::Suffix => Resolution::Rename(create_suffixed_names(destination)?),
+        OnConflict::Number => Resolution::Rename(create_numbered_names(destination)?),
+        OnConflict::Overwrite => Resolution::Overwrite {
+            set_aside: find_free_names(|n| {
+                let fname = destination.file_name().unwrap_or_default().to_string_lossy();
+                destination.with_file_name(format!(".renamer-old{}_{}", n, fname))
+            })?,
+        },
+        OnConflict::Backup => Resolution::Backup(create_backup_names(destination)?),
+    };
+
+    Ok(resolution)
+}
+
+fn create_suffixed_names(path: &Path) -> io::Result<PathBuf> {
+    let (stem, ext) = split_file_names(path);
+    let suffixed = path.with_file_name(format!("{}_renamerdup{}", stem, ext));
+    if !is_taken(&suffixed) {
+        return Ok(suffixed);
+    }
+
+    find_free_names(|n| path.with_file_name(format!("{}_renamerdup_{}{}", stem, n, ext)))
+}
+
+fn create_numbered_names(path: &Path) -> io::Result<PathBuf> {
+    let (stem, ext) = split_file_names(path);
+    find_free_names(|n| path.with_file_name(format!("{}_{}{}", stem, n, ext)))
+}
+
+fn create_backup_names(path: &Path) -> io::Result<PathBuf> {
+    let fname = path.file_name().unwrap_or_default().to_string_lossy();
+    let backup = path.with_file_name(format!("{}.bak", fname));
+    if !is_taken(&backup) {
+        return Ok(backup);
+    }
+
+    find_free_names(|n| path.with_file_name(format!("{}.bak.{}", fname, n)))
+}
+
+// Counts from 1 until the name is not taken.
+fn find_free_names<F: Fn(u32) -> PathBuf>(names: F) -> io::Result<PathBuf> {
+    (1..=u32::MAX)
+        .map(names)
+        .find(|path| !is_taken(path))
+        .ok_or_else(|| io::Error::other("RUN OUT OF NUMBERS FOR NEW NAMES"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn create_duplicat_name_test() {
+        let fname = PathBuf::from("data/valid.fastq.gz");
+        let fname_fq = PathBuf::from("data/valid.fq.gz");
+        let res = PathBuf::from("data/valid_renamerdup.fastq.gz");
+        let res_fq = PathBuf::from("data/valid_renamerdup.fq.gz");
+
+        assert_eq!(res, create_suffixed_names(&fname).unwrap());
+        assert_eq!(res_fq, create_suffixed_names(&fname_fq).unwrap());
+        assert_eq!(PathBuf::from("data/README_renamerdup"), create_suffixed_names(Path::new("data/README")).unwrap());
+        assert_eq!(PathBuf::from("README_renamerdup"), create_suffixed_names(Path::new("README")).unwrap());
+    }
+
+    #[test]
+    fn check_duplicate_test() {
+        let prop_name = PathBuf::from("test_files/valid.fastq.gz"); // exist in test_files dir
+        let prop_fq = PathBuf::from("test_files/valid2.fq.gz"); // exist in test_files dir
+
+        let new_names = PathBuf::from("test_files/valid_renamerdup.fastq.gz");
+        let new_fq = PathBuf::from("test_files/valid2_renamerdup.fq.gz");
+
+        assert_eq!(Resolution::Rename(new_names), resolve_conflict(&prop_name, OnConflict::Suffix).unwrap());
+        assert_eq!(Resolution::Rename(new_fq), resolve_conflict(&prop_fq, OnConflict::Suffix).unwrap());
+        assert_eq!(Resolution::Free, resolve_conflict(Path::new("test_files/free.fq"), OnConflict::Error).unwrap());
+    }
+
+    // Numbers and suffixes never land on an existing file.
+    #[test]
+    fn find_free_names_test() {
+        let dir = env::temp_dir().join("renamer_conflict_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        ["a.fq.gz", "a_1.fq.gz", "a_renamerdup.fq.gz", "a.fq.gz.bak"].iter()
+            .for_each(|f| { fs::File::create(dir.join(f)).unwrap(); });
+        let taken = dir.join("a.fq.gz");
+
+        assert_eq!(dir.join("a_2.fq.gz"), create_numbered_names(&taken).unwrap());
+        assert_eq!(dir.join("a_renamerdup_1.fq.gz"), create_suffixed_names(&taken).unwrap());
+        assert_eq!(Resolution::Backup(dir.join("a.fq.gz.bak.1")), resolve_conflict(&taken, OnConflict::Backup).unwrap());
+        assert_eq!(Resolution::Skip, resolve_conflict(&taken, OnConflict::Skip).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn parse_on_conflict_test() {
+        assert_eq!(Ok(OnConflict::Number), OnConflict::parse("number"));
+        assert_eq!(Ok(OnConflict::Backup), OnConflict::parse("backup"));
+        assert!(OnConflict::parse("rename").is_err());
+    }
+}
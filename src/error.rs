@@ -0,0 +1,149 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Exit codes for scripts and workflow managers.
+// A successful run exits with 0.
+// Usage errors from the command line also exit with INVALID_INPUT.
+pub const INVALID_INPUT: i32 = 1;
+pub const VALIDATION_FAILED: i32 = 2;
+pub const PARTIAL_RENAME: i32 = 3;
+pub const ABORTED: i32 = 4;
+
+// Errors across the app.
+// Each carries the path or the line of the input it came from.
+#[derive(Debug)]
+pub enum Error {
+    Io { path: PathBuf, source: io::Error },
+    InvalidInput { path: PathBuf, msg: String },
+    ColumnNotFound(String),
+    // Placeholder in the template that is neither a built-in nor a column.
+    Placeholder(String),
+    InvalidRow { line: usize, msg: String },
+    Duplicate { kind: &'static str, path: PathBuf, line: usize, prev: usize },
+    InvalidJournal { path: PathBuf, line: usize },
+    UnfinishedJournal(PathBuf),
+    Rename { origin: PathBuf, destination: PathBuf, source: io::Error },
+    // All problems found in the rename table.
+    Table(Vec<Error>),
+    // New names taken by files outside the batch.
+    Conflicts(Vec<PathBuf>),
+    // Errors found when checking the files before renaming.
+    Validation(u32),
+    // Files whose checksums don't match.
+    Mismatch(Vec<PathBuf>),
+    // Some files were not renamed. The rest were.
+    Partial { done: u32, failed: u32 },
+    // The user aborted. The renamed files were rolled back.
+    Aborted,
+}
+
+impl Error {
+    // For map_err on file access.
+    pub fn io(path: &Path) -> impl FnOnce(io::Error) -> Self + '_ {
+        move |source| Error::Io { path: path.to_path_buf(), source }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Validation(_) | Error::Conflicts(_) | Error::Mismatch(_) => VALIDATION_FAILED,
+            Error::Partial { .. } => PARTIAL_RENAME,
+            Error::Aborted => ABORTED,
+            _ => INVALID_INPUT,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "CAN'T ACCESS {:?}. {}", path, source),
+            Error::InvalidInput { path, msg } => write!(f, "INVALID INPUT {:?}! {}", path, msg),
+            Error::ColumnNotFound(col) => write!(f, "COLUMN {} NOT FOUND IN THE HEADER.", col),
+            Error::Placeholder(name) => write!(f,
+                "UNKNOWN PLACEHOLDER {{{}}} IN THE TEMPLATE. \
+                USE A COLUMN NAME, A COLUMN NUMBER, stem, ext, row, OR parent.", name
+            ),
+            Error::InvalidRow { line, msg } => {
+                write!(f, "INVALID INPUT! {} IN LINE {}.", msg, line)
+            }
+            Error::Duplicate { kind, path, line, prev } => write!(f,
+                "DUPLICATE {} {:?} IN LINE {}. ALREADY LISTED IN LINE {}.",
+                kind, path, line, prev
+            ),
+            Error::InvalidJournal { path, line } => {
+                write!(f, "INVALID JOURNAL {:?}. CAN'T READ LINE {}.", path, line)
+            }
+            Error::UnfinishedJournal(path) => {
+                write!(f, "UNFINISHED JOURNAL {:?}. RUN `renamer recover` FIRST.", path)
+            }
+            Error::Rename { origin, destination, source } => {
+                write!(f, "CAN'T RENAME {:?} TO {:?}. {}", origin, destination, source)
+            }
+            Error::Table(errors) => {
+                write!(f, "ERRORS FOUND IN THE INPUT: {}", errors.len())?;
+                errors.iter().try_for_each(|e| write!(f, "\n{}", e))
+            }
+            Error::Conflicts(paths) => {
+                write!(f, "NEW NAMES ALREADY TAKEN: {}", paths.len())?;
+                paths.iter().try_for_each(|p| write!(f, "\n{:?}", p))
+            }
+            Error::Validation(counts) => {
+                write!(f, "INPUT CHECK FAILED. ERRORS FOUND: {}", counts)
+            }
+            Error::Mismatch(paths) => {
+                write!(f, "CHECKSUMS DON'T MATCH: {}", paths.len())?;
+                paths.iter().try_for_each(|p| write!(f, "\n{:?}", p))
+            }
+            Error::Partial { done, failed } => write!(f,
+                "PARTIAL RENAMING. {} FILES RENAMED, {} FILES NOT RENAMED. SEE THE JOURNAL.",
+                done, failed
+            ),
+            Error::Aborted => write!(f, "RENAMING ABORTED. THE RENAMED FILES WERE ROLLED BACK."),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } | Error::Rename { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display_table_errors_test() {
+        let errors = Error::Table(vec![
+            Error::InvalidRow { line: 3, msg: String::from("ONLY ONE COLUMN FOUND") },
+            Error::Duplicate {
+                kind: "SOURCE",
+                path: PathBuf::from("data/a.fq"),
+                line: 5,
+                prev: 2
+            },
+        ]);
+
+        assert_eq!("ERRORS FOUND IN THE INPUT: 2\n\
+            INVALID INPUT! ONLY ONE COLUMN FOUND IN LINE 3.\n\
+            DUPLICATE SOURCE \"data/a.fq\" IN LINE 5. ALREADY LISTED IN LINE 2.",
+            errors.to_string()
+        );
+    }
+
+    #[test]
+    fn exit_code_test() {
+        assert_eq!(INVALID_INPUT, Error::ColumnNotFound(String::from("id")).exit_code());
+        assert_eq!(INVALID_INPUT, Error::Table(Vec::new()).exit_code());
+        assert_eq!(VALIDATION_FAILED, Error::Validation(2).exit_code());
+        assert_eq!(VALIDATION_FAILED, Error::Mismatch(Vec::new()).exit_code());
+        assert_eq!(PARTIAL_RENAME, Error::Partial { done: 1, failed: 1 }.exit_code());
+        assert_eq!(ABORTED, Error::Aborted.exit_code());
+    }
+}
@@ -0,0 +1,139 @@
+use std::path::Path;
+use std::sync::RwLock;
+
+// Extensions made of more than one part.
+// They are kept together when matching extensions, naming duplicates,
+// and finding files, so .fastq.gz is never treated as just .gz.
+const COMPOUND_EXTENSIONS: &[&str] = &[
+    ".fastq.gz", ".fq.gz", ".fastq.gzip", ".fq.gzip",
+    ".fastq.bz2", ".fq.bz2", ".fastq.xz", ".fq.xz", ".fastq.zst", ".fq.zst",
+    ".fasta.gz", ".fa.gz", ".fna.gz", ".fas.gz", ".fasta.bz2", ".fa.bz2",
+    ".sam.gz", ".bam.bai", ".bam.csi", ".cram.crai",
+    ".vcf.gz", ".vcf.bgz", ".vcf.gz.tbi", ".vcf.gz.csi", ".g.vcf.gz", ".bcf.csi",
+    ".bed.gz", ".bed.gz.tbi", ".gff.gz", ".gff3.gz", ".gtf.gz",
+    ".tar.gz", ".tar.bz2", ".tar.xz", ".tar.zst",
+];
+
+// Extensions added by the user with --compound-ext.
+static USER_EXTENSIONS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+// Accepts extensions with or without the leading dot,
+// such as fastq.lz4 or .fastq.lz4. A single part is not compound.
+pub fn parse_compound_ext(ext: &str) -> Result<String, String> {
+    let ext = format!(".{}", ext.trim().trim_start_matches('.'));
+    let parts: Vec<&str> = ext[1..].split('.').collect();
+    if parts.len() < 2 || parts.iter().any(|p| p.is_empty()) {
+        return Err(format!("Invalid compound extension: {}. Use parts separated by dots, such as fastq.lz4.", ext));
+    }
+
+    Ok(ext.to_ascii_lowercase())
+}
+
+pub fn register_extensions(exts: &[String]) {
+    let mut registry = USER_EXTENSIONS.write().expect("EXTENSION REGISTRY IS POISONED");
+    for ext in exts {
+        if !registry.contains(ext) {
+            registry.push(ext.to_string());
+        }
+    }
+}
+
+// Splits the file name into its stem and extension.
+// The longest known compound extension wins, so .vcf.gz.tbi beats .gz.
+// Matching ignores case. Hidden files without extension, such as .bashrc,
+// have no extension.
+pub fn split_file_names(path: &Path) -> (String, String) {
+    let fname = path.file_name().unwrap_or_default().to_string_lossy();
+    let lower = fname.to_ascii_lowercase();
+    let registry = USER_EXTENSIONS.read().expect("EXTENSION REGISTRY IS POISONED");
+    let compound = COMPOUND_EXTENSIONS.iter()
+        .copied()
+        .chain(registry.iter().map(|ext| ext.as_str()))
+        .filter(|ext| lower.len() > ext.len() && lower.ends_with(ext))
+        .map(|ext| ext.len())
+        .max();
+
+    let idx = match compound {
+        Some(len) => fname.len() - len,
+        None => match fname.rfind('.') {
+            Some(idx) if idx > 0 => idx,
+            _ => return (fname.into_owned(), String::new()),
+        },
+    };
+
+    let (stem, ext) = fname.split_at(idx);
+    (stem.to_string(), ext.to_string())
+}
+
+// Returns the full extension with the leading dot, such as .fastq.gz.
+pub fn get_extension(path: &Path) -> String {
+    split_file_names(path).1
+}
+
+// Checks the file against an extension with or without the leading dot.
+// Any of the final parts count, so gz matches .fastq.gz.
+pub fn has_extension(path: &Path, ext: &str) -> bool {
+    let ext = format!(".{}", ext.trim_start_matches('.')).to_ascii_lowercase();
+    let fname = path.file_name().unwrap_or_default().to_string_lossy().to_ascii_lowercase();
+    fname.len() > ext.len() && fname.ends_with(&ext)
+}
+
+// Compressed fastq files, including compound extensions added by the user.
+pub fn is_compressed_fastq(path: &Path) -> bool {
+    let ext = get_extension(path).to_ascii_lowercase();
+    ext.starts_with(".fastq.") || ext.starts_with(".fq.")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_file_names_test() {
+        let split = |p: &str| split_file_names(Path::new(p));
+
+        assert_eq!(("valid".to_string(), ".fastq.gz".to_string()), split("data/valid.fastq.gz"));
+        assert_eq!(("valid2".to_string(), ".fq.gz".to_string()), split("valid2.fq.gz"));
+        assert_eq!(("sample".to_string(), ".vcf.gz.tbi".to_string()), split("sample.vcf.gz.tbi"));
+        assert_eq!(("Sample".to_string(), ".FQ.GZ".to_string()), split("Sample.FQ.GZ"));
+        assert_eq!(("sample.R1".to_string(), ".csv".to_string()), split("sample.R1.csv"));
+        assert_eq!(("README".to_string(), String::new()), split("data/README"));
+        assert_eq!((".bashrc".to_string(), String::new()), split(".bashrc"));
+    }
+
+    #[test]
+    fn register_extensions_test() {
+        let path = Path::new("data/sample.fastq.lz4");
+        assert_eq!(".lz4", get_extension(path));
+
+        let ext = parse_compound_ext("FASTQ.lz4").unwrap();
+        register_extensions(&[ext]);
+        assert_eq!(".fastq.lz4", get_extension(path));
+    }
+
+    #[test]
+    fn parse_compound_ext_test() {
+        assert_eq!(Ok(String::from(".fq.lz4")), parse_compound_ext("fq.lz4"));
+        assert_eq!(Ok(String::from(".fq.lz4")), parse_compound_ext(".fq.lz4"));
+        assert!(parse_compound_ext("lz4").is_err());
+        assert!(parse_compound_ext("fq..lz4").is_err());
+    }
+
+    #[test]
+    fn has_extension_test() {
+        assert!(has_extension(Path::new("a.fastq.gz"), "fastq.gz"));
+        assert!(has_extension(Path::new("a.fastq.gz"), ".gz"));
+        assert!(!has_extension(Path::new("a.fastq.gz"), "fq.gz"));
+        assert!(!has_extension(Path::new("afastq"), "fastq"));
+        assert!(!has_extension(Path::new(".csv"), "csv"));
+    }
+
+    #[test]
+    fn is_compressed_fastq_test() {
+        assert!(is_compressed_fastq(Path::new("a.fastq.gz")));
+        assert!(is_compressed_fastq(Path::new("a.FQ.BZ2")));
+        assert!(is_compressed_fastq(Path::new("a.fastq.zst")));
+        assert!(!is_compressed_fastq(Path::new("a.fastq")));
+        assert!(!is_compressed_fastq(Path::new("a.fasta.gz")));
+    }
+}
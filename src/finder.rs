@@ -1,19 +1,22 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use walkdir::WalkDir;
 
+use crate::checksum::Algorithm;
+use crate::error::Error;
+use crate::extension;
 use crate::writer;
 
-pub fn process_input_dir(path: &str, ext: &str, bpa: bool) {
+pub fn process_input_dir(path: &str, ext: &str, bpa: bool, checksum: Option<Algorithm>) -> Result<(), Error> {
     let mut entries = traverse_dir(path, ext);
-    println!("Found {} files", entries.len());
-    writer::write_to_csv(&mut entries, bpa).unwrap();
+    info!("Found {} files", entries.len());
+    writer::write_to_csv(&mut entries, bpa, checksum)
 }
 
-pub fn process_input_wcard(files: &[&str], bpa: bool) {
+pub fn process_input_wcard(files: &[&str], bpa: bool, checksum: Option<Algorithm>) -> Result<(), Error> {
     let mut entries = convert_wcard_to_path(files);
-    println!("Found {} files", entries.len());
-    writer::write_to_csv(&mut entries, bpa).unwrap();
+    info!("Found {} files", entries.len());
+    writer::write_to_csv(&mut entries, bpa, checksum)
 }
 
 fn convert_wcard_to_path(files: &[&str]) -> Vec<PathBuf> {
@@ -27,28 +30,48 @@ fn traverse_dir(path: &str, ext: &str) -> Vec<PathBuf> {
         .filter_map(|recs| recs.ok())
         .for_each(|e| {
             let files = String::from(e.path().to_string_lossy());
-            if ext == "fastq" {
-                match_fastq(&files, &mut entries);
-            } else {
-                match_any(&files, ext, &mut entries);
-            }
+            match_files(&files, ext, &mut entries);
         });
     
     entries
 }
 
+// Files only, sorted by path. Without an extension, all files are found.
+pub fn find_files(path: &str, ext: Option<&str>) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = Vec::new();
+
+    WalkDir::new(path).into_iter()
+        .filter_map(|recs| recs.ok())
+        .filter(|e| e.file_type().is_file())
+        .for_each(|e| {
+            let files = String::from(e.path().to_string_lossy());
+            match ext {
+                Some(ext) => match_files(&files, ext, &mut entries),
+                None => entries.push(PathBuf::from(files)),
+            }
+        });
+
+    entries.sort();
+    entries
+}
+
+fn match_files(files: &str, ext: &str, entries: &mut Vec<PathBuf>) {
+    if ext == "fastq" {
+        match_fastq(files, entries);
+    } else {
+        match_any(files, ext, entries);
+    }
+}
+
+// Compressed fastq files, following the known compound extensions.
 fn match_fastq(files: &str, entries: &mut Vec<PathBuf>) {
-    match files {
-        s if s.ends_with(".fastq.gz") => entries.push(PathBuf::from(files)),
-        s if s.ends_with(".fq.gz") => entries.push(PathBuf::from(files)),
-        s if s.ends_with("fastq.gzip") => entries.push(PathBuf::from(files)),
-        s if s.ends_with("fq.gzip") => entries.push(PathBuf::from(files)),
-        _ => (),
-    };
+    if extension::is_compressed_fastq(Path::new(files)) {
+        entries.push(PathBuf::from(files))
+    }
 }
 
 fn match_any(files: &str, ext: &str, entries: &mut Vec<PathBuf>) {
-    if files.ends_with(ext) {
+    if extension::has_extension(Path::new(files), ext) {
         entries.push(PathBuf::from(files))
     }
 }
@@ -61,7 +84,7 @@ mod test {
     fn traverse_dir_fastq_test() {
         let path = "test_files/";
         let ext = "fastq";
-        let res = traverse_dir(&path, &ext);
+        let res = traverse_dir(path, ext);
 
         assert_eq!(3, res.len());
     }
@@ -70,24 +93,36 @@ mod test {
     fn traverse_dir_any_test() {
         let path = "test_files/";
         let ext = "csv";
-        let res = traverse_dir(&path, &ext);
+        let res = traverse_dir(path, ext);
 
         assert_eq!(3, res.len());
     }
 
+    #[test]
+    fn find_files_test() {
+        let res = find_files("test_files/", Some("csv"));
+        assert_eq!(3, res.len());
+        assert!(res.windows(2).all(|w| w[0] <= w[1]));
+
+        let all = find_files("test_files/", None);
+        assert!(all.len() > res.len());
+        assert!(all.iter().all(|f| f.is_file()));
+    }
+
     #[test]
     fn match_fastq_test() {
         let path_1 = "Bunomys_andrewsi.fastq.gz";
         let path_2 = "Bunomys_chrysocomus.fastq.gz";
         let path_3 = "Bunomys_chrysocomus.fasta";
-        let files = vec![path_1, path_2, path_3];
+        let path_4 = "Bunomys_chrysocomus.fq.bz2";
+        let files = [path_1, path_2, path_3, path_4];
 
         let mut entries = Vec::new();
         files.iter()
             .for_each(|e|
                 match_fastq(e, &mut entries)
             );
-        assert_eq!(2, entries.len());
+        assert_eq!(3, entries.len());
     }
 
     #[test]
@@ -98,12 +133,12 @@ mod test {
         let path_4 = "Bunomys_chrysocomus.fasta";
         let ext = "csv";
 
-        let files = vec![path_1, path_2, path_3, path_4];
+        let files = [path_1, path_2, path_3, path_4];
         
         let mut entries = Vec::new();
         files.iter()
             .for_each(|e|
-                match_any(e, &ext, &mut entries)
+                match_any(e, ext, &mut entries)
             );
         assert_eq!(3, entries.len());
     }
@@ -0,0 +1,478 @@
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, ErrorKind, LineWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use clap::crate_version;
+
+use crate::checksum::Algorithm;
+use crate::conflict::OnConflict;
+use crate::error::Error;
+use crate::planner::Step;
+use crate::policy::RunOpts;
+use crate::transfer::Mode;
+
+const JOURNAL_PREFIX: &str = "renamer-journal_";
+
+// The journal is a tab separated file.
+// Metadata lines start with '#' and are followed by
+// one line for each renaming: status, origin, destination.
+// The whole plan is written first. Each renaming then writes
+// its intent (pending) before touching the file and its outcome after.
+// A journal without the finished line is an interrupted run.
+// Moved is a renaming across filesystems, done by copying the file.
+// Its checksum is kept in a metadata line after it, as for copies.
+// Removed is a copy or link deleted by undo or rollback.
+// Pending aside is the intent to move a file out of the way of a new name.
+// Backed up is a file moved out of the way to keep it.
+// Set aside is a file moved out of the way to be overwritten.
+// Overwritten is a file replaced by a new name, origin first.
+// Its set-aside file is deleted then.
+// Paths are escaped, so names with tabs, newlines,
+// or bytes that are not UTF-8 keep to one line and read back as they were.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Planned,
+    PlannedTemp,
+    Pending,
+    Renamed,
+    Moved,
+    Copied,
+    Linked,
+    Symlinked,
+    Removed,
+    PendingAside,
+    BackedUp,
+    SetAside,
+    Overwritten,
+    Skipped,
+    NotFound,
+    RolledBack,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Status::Planned => "planned",
+            Status::PlannedTemp => "planned-temp",
+            Status::Pending => "pending",
+            Status::Renamed => "renamed",
+            Status::Moved => "moved",
+            Status::Copied => "copied",
+            Status::Linked => "linked",
+            Status::Symlinked => "symlinked",
+            Status::Removed => "removed",
+            Status::PendingAside => "pending-aside",
+            Status::BackedUp => "backed-up",
+            Status::SetAside => "set-aside",
+            Status::Overwritten => "overwritten",
+            Status::Skipped => "skipped",
+            Status::NotFound => "not-found",
+            Status::RolledBack => "rolled-back",
+        }
+    }
+
+    fn parse(status: &str) -> Option<Self> {
+        match status {
+            "planned" => Some(Status::Planned),
+            "planned-temp" => Some(Status::PlannedTemp),
+            "pending" => Some(Status::Pending),
+            "renamed" => Some(Status::Renamed),
+            "moved" => Some(Status::Moved),
+            "copied" => Some(Status::Copied),
+            "linked" => Some(Status::Linked),
+            "symlinked" => Some(Status::Symlinked),
+            "removed" => Some(Status::Removed),
+            "pending-aside" => Some(Status::PendingAside),
+            "backed-up" => Some(Status::BackedUp),
+            "set-aside" => Some(Status::SetAside),
+            "overwritten" => Some(Status::Overwritten),
+            "skipped" => Some(Status::Skipped),
+            "not-found" => Some(Status::NotFound),
+            "rolled-back" => Some(Status::RolledBack),
+            _ => None,
+        }
+    }
+
+    // The file is under its new name.
+    pub fn is_done(&self) -> bool {
+        matches!(self,
+            Status::Renamed | Status::Moved | Status::Copied | Status::Linked | Status::Symlinked
+            | Status::BackedUp | Status::SetAside
+        )
+    }
+
+    // The file held a new name and was moved out of its way.
+    pub fn is_aside(&self) -> bool {
+        matches!(self, Status::BackedUp | Status::SetAside)
+    }
+
+    // The original file is still in place.
+    pub fn keeps_origin(&self) -> bool {
+        matches!(self, Status::Copied | Status::Linked | Status::Symlinked)
+    }
+}
+
+// Journals are never renamed along with the files they describe.
+pub fn is_journal(path: &Path) -> bool {
+    path.file_name()
+        .map(|f| f.to_string_lossy().starts_with(JOURNAL_PREFIX))
+        .unwrap_or(false)
+}
+
+pub struct Journal {
+    path: PathBuf,
+    line: LineWriter<File>,
+}
+
+impl Journal {
+    // Journal is saved next to the input file,
+    // or inside the input directory for the regex and sanitize commands.
+    // The run settings are kept, so an interrupted run resumes the same way.
+    pub fn create(input: &str, run: &RunOpts) -> Result<Self, Error> {
+        let now = Local::now();
+        let stem = format!("{}{}", JOURNAL_PREFIX, now.format("%Y%m%d_%H%M%S"));
+        let dir = match Path::new(input).is_dir() {
+            true => Path::new(input),
+            false => Path::new(input).parent().unwrap_or_else(|| Path::new("")),
+        };
+        let (path, file) = create_unique(dir, &stem).map_err(Error::io(dir))?;
+        let mut journal = Self { path, line: LineWriter::new(file) };
+        journal.write_header(input, &now.to_rfc3339(), run)
+            .map_err(Error::io(&journal.path))?;
+
+        Ok(journal)
+    }
+
+    fn write_header(&mut self, input: &str, date: &str, run: &RunOpts) -> io::Result<()> {
+        writeln!(self.line, "#renamer\tv{}", crate_version!())?;
+        writeln!(self.line, "#date\t{}", date)?;
+        writeln!(self.line, "#input\t{}", input)?;
+        writeln!(self.line, "#workdir\t{}", escape_path(&env::current_dir()?))?;
+        writeln!(self.line, "#mode\t{}", run.mode.as_str())?;
+        writeln!(self.line, "#on_conflict\t{}", run.on_conflict.as_str())?;
+        if let Some(algo) = run.checksum {
+            writeln!(self.line, "#checksum\t{}", algo.as_str())?;
+        }
+        writeln!(self.line, "status\torigin\tdestination")
+    }
+
+    // Reopens an interrupted journal to append the recovery.
+    pub fn open(path: &str) -> Result<Self, Error> {
+        let path = PathBuf::from(path);
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(Error::io(&path))?;
+
+        Ok(Self { path, line: LineWriter::new(file) })
+    }
+
+    pub fn record_plan(&mut self, steps: &[Step]) -> Result<(), Error> {
+        for step in steps {
+            let status = if step.temporary {
+                Status::PlannedTemp
+            } else {
+                Status::Planned
+            };
+            self.record(status, &step.origin, &step.destination)?;
+        }
+
+        self.sync()
+    }
+
+    // The intent must reach the disk before the file is renamed.
+    pub fn record_intent(&mut self, origin: &Path, destination: &Path) -> Result<(), Error> {
+        self.record(Status::Pending, origin, destination)?;
+        self.sync()
+    }
+
+    // Same as the intent of a renaming, for a file moved out of the way.
+    pub fn record_aside_intent(&mut self, taken: &Path, set_aside: &Path) -> Result<(), Error> {
+        self.record(Status::PendingAside, taken, set_aside)?;
+        self.sync()
+    }
+
+    pub fn finish(&mut self) -> Result<(), Error> {
+        writeln!(self.line, "#finished\t{}", Local::now().to_rfc3339())
+            .map_err(Error::io(&self.path))?;
+        self.sync()
+    }
+
+    fn sync(&mut self) -> Result<(), Error> {
+        self.line.flush()
+            .and_then(|_| self.line.get_ref().sync_data())
+            .map_err(Error::io(&self.path))
+    }
+
+    pub fn record(&mut self, status: Status, origin: &Path, destination: &Path) -> Result<(), Error> {
+        writeln!(self.line, "{}\t{}\t{}",
+            status.as_str(),
+            escape_path(origin),
+            escape_path(destination)
+        )
+        .map_err(Error::io(&self.path))
+    }
+
+    pub fn record_checksum(&mut self, path: &Path, checksum: &str) -> Result<(), Error> {
+        writeln!(self.line, "#sha256\t{}\t{}", checksum, escape_path(path))
+            .map_err(Error::io(&self.path))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+// Never overwrite an existing journal.
+// Runs that start within the same second get a numbered journal.
+fn create_unique(dir: &Path, stem: &str) -> io::Result<(PathBuf, File)> {
+    let mut path = dir.join(format!("{}.tsv", stem));
+    let mut count = 1;
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                path = dir.join(format!("{}_{}.tsv", stem, count));
+                count += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+pub struct Record {
+    pub status: Status,
+    pub origin: PathBuf,
+    pub destination: PathBuf,
+}
+
+pub struct Log {
+    pub records: Vec<Record>,
+    pub finished: bool,
+    pub mode: Mode,
+    pub on_conflict: OnConflict,
+    pub checksum: Option<Algorithm>,
+}
+
+// Paths in the journal are relative to the directory
+// the original run was executed from. We resolve them here,
+// so the journal can be undone from anywhere.
+pub fn read_journal(path: &str) -> Result<Log, Error> {
+    let file = File::open(path).map_err(Error::io(Path::new(path)))?;
+    let buff = BufReader::new(file);
+
+    let mut workdir = PathBuf::new();
+    let mut records = Vec::new();
+    let mut finished = false;
+    let mut mode = Mode::Rename;
+    let mut on_conflict = OnConflict::default();
+    let mut checksum = None;
+    for (i, line) in buff.lines().enumerate() {
+        let line = line.map_err(Error::io(Path::new(path)))?;
+        let invalid_journal = || Error::InvalidJournal { path: PathBuf::from(path), line: i + 1 };
+        if let Some(meta) = line.strip_prefix("#workdir\t") {
+            workdir = unescape_path(meta).ok_or_else(invalid_journal)?;
+            continue;
+        }
+
+        if let Some(meta) = line.strip_prefix("#mode\t") {
+            mode = Mode::parse(meta).map_err(|_| invalid_journal())?;
+            continue;
+        }
+
+        if let Some(meta) = line.strip_prefix("#on_conflict\t") {
+            on_conflict = OnConflict::parse(meta).map_err(|_| invalid_journal())?;
+            continue;
+        }
+
+        if let Some(meta) = line.strip_prefix("#checksum\t") {
+            checksum = Some(Algorithm::parse(meta).map_err(|_| invalid_journal())?);
+            continue;
+        }
+
+        if line.starts_with("#finished\t") {
+            finished = true;
+            continue;
+        }
+
+        if line.starts_with('#') || line.starts_with("status\t") || line.is_empty() {
+            continue;
+        }
+
+        let cols: Vec<&str> = line.split('\t').collect();
+        let status = cols.first().and_then(|s| Status::parse(s));
+        let paths = cols.get(1).zip(cols.get(2))
+            .and_then(|(origin, destination)| unescape_path(origin).zip(unescape_path(destination)));
+        match (status, paths, cols.len()) {
+            (Some(status), Some((origin, destination)), 3) => records.push(Record {
+                status,
+                origin: workdir.join(origin),
+                destination: workdir.join(destination),
+            }),
+            _ => return Err(invalid_journal()),
+        }
+    }
+
+    Ok(Log { records, finished, mode, on_conflict, checksum })
+}
+
+// Backslashes, tabs, and line breaks are escaped with a backslash.
+// Bytes that are not UTF-8 are written as \xHH.
+fn escape_path(path: &Path) -> String {
+    let mut escaped = String::new();
+    for chunk in get_path_bytes(path).utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '\t' => escaped.push_str("\\t"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                c => escaped.push(c),
+            }
+        }
+        chunk.invalid().iter().for_each(|b| escaped.push_str(&format!("\\x{:02x}", b)));
+    }
+
+    escaped
+}
+
+// None if the escape is unknown.
+fn unescape_path(field: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::new();
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buff = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buff).as_bytes());
+            continue;
+        }
+
+        let byte = match chars.next()? {
+            '\\' => b'\\',
+            't' => b'\t',
+            'n' => b'\n',
+            'r' => b'\r',
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                if hex.len() != 2 || !hex.chars().all(|h| h.is_ascii_hexdigit()) {
+                    return None;
+                }
+                u8::from_str_radix(&hex, 16).ok()?
+            }
+            _ => return None,
+        };
+        bytes.push(byte);
+    }
+
+    Some(get_path_from_bytes(bytes))
+}
+
+#[cfg(unix)]
+fn get_path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn get_path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().as_bytes().to_vec()
+}
+
+#[cfg(unix)]
+fn get_path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn get_path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+pub fn display_journal(journal: &Journal) {
+    info!("The journal is saved as {}", journal.path().display());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn journal_roundtrip_test() {
+        let dir = env::temp_dir().join("renamer_journal_roundtrip_test");
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.csv");
+
+        let old = PathBuf::from("data/old.fq.gz");
+        let new = PathBuf::from("data/new.fq.gz");
+        let run = RunOpts {
+            mode: Mode::Copy,
+            on_conflict: OnConflict::Backup,
+            checksum: Some(Algorithm::Md5),
+            ..RunOpts::default()
+        };
+        let mut journal = Journal::create(&input.to_string_lossy(), &run).unwrap();
+        journal.record(Status::Copied, &old, &new).unwrap();
+        journal.record_checksum(&new, "e3b0c442").unwrap();
+        journal.record(Status::NotFound, &new, &old).unwrap();
+
+        let path = journal.path().to_string_lossy().into_owned();
+        assert!(!read_journal(&path).unwrap().finished);
+        journal.finish().unwrap();
+
+        let log = read_journal(&path).unwrap();
+        let records = log.records;
+        let workdir = env::current_dir().unwrap();
+        assert!(log.finished);
+        assert_eq!(Mode::Copy, log.mode);
+        assert_eq!(OnConflict::Backup, log.on_conflict);
+        assert_eq!(Some(Algorithm::Md5), log.checksum);
+
+        assert_eq!(2, records.len());
+        assert_eq!(Status::Copied, records[0].status);
+        assert_eq!(workdir.join(&old), records[0].origin);
+        assert_eq!(workdir.join(&new), records[0].destination);
+        assert_eq!(Status::NotFound, records[1].status);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn journal_escaped_names_test() {
+        let dir = env::temp_dir().join("renamer_journal_escaped_test");
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.csv");
+
+        let old = PathBuf::from("data/a\nb\tc.fq");
+        let new = PathBuf::from("data/a\\b\r.fq");
+        let mut journal = Journal::create(&input.to_string_lossy(), &RunOpts::default()).unwrap();
+        journal.record(Status::Renamed, &old, &new).unwrap();
+        journal.finish().unwrap();
+
+        let path = journal.path().to_string_lossy().into_owned();
+        let records = read_journal(&path).unwrap().records;
+        let workdir = env::current_dir().unwrap();
+        assert_eq!(1, records.len());
+        assert_eq!(workdir.join(&old), records[0].origin);
+        assert_eq!(workdir.join(&new), records[0].destination);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escape_non_utf8_path_test() {
+        let path = get_path_from_bytes(vec![b'a', 0xff, b'\t', b'b']);
+        assert_eq!("a\\xff\\tb", escape_path(&path));
+        assert_eq!(Some(path), unescape_path("a\\xff\\tb"));
+        assert_eq!(None, unescape_path("a\\q"));
+        assert_eq!(None, unescape_path("a\\x+f"));
+    }
+
+    #[test]
+    fn read_invalid_journal_test() {
+        let input = "test_files/input.csv";
+        assert!(read_journal(input).is_err());
+    }
+}
@@ -1,26 +1,51 @@
 // Heru Handika
 // 16 January 2021
 
+#[macro_use]
+mod output;
+
 mod cli;
 mod checker;
+mod checksum;
+mod companion;
+mod conflict;
+mod error;
+mod extension;
 mod finder;
+mod journal;
 mod parser;
+mod plan;
+mod planner;
+mod policy;
+mod portable;
+mod recover;
 mod renamer;
+mod replacer;
+mod report;
+mod sanitizer;
+mod spreadsheet;
+mod template;
+mod transfer;
 mod writer;
 
 use clap::crate_version;
 
+use std::process;
 use std::time::Instant;
 
 fn main() {
     let version = crate_version!();
 
     let tnow = Instant::now();
-    cli::get_cli(&version);
+    if let Err(e) = cli::get_cli(version) {
+        output::clear_progress();
+        eprintln!("{} {}", output::red("ERROR:"), e);
+        process::exit(e.exit_code());
+    }
     let elapsed = tnow.elapsed(); 
     
-    println!("\nExecution time: {:?}", elapsed);
-    println!("Thank you for using renamer v{}! 🙏", &version);
+    info!("\nExecution time: {:?}", elapsed);
+    info!("Thank you for using renamer v{}! 🙏", &version);
     
 }
 
@@ -0,0 +1,200 @@
+use std::env;
+use std::fmt::Display;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+// Batches this large show a progress bar instead of a line per file.
+const LARGE_BATCH: usize = 100;
+
+const BAR_WIDTH: usize = 40;
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+static PROGRESS_SHOWN: AtomicBool = AtomicBool::new(false);
+
+// Prints unless quiet.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            $crate::output::clear_progress();
+            println!($($arg)*);
+        }
+    };
+}
+
+// Prints at every level, clearing the progress bar first.
+// For errors and warnings about a file.
+macro_rules! warn {
+    ($($arg:tt)*) => {{
+        $crate::output::clear_progress();
+        println!($($arg)*);
+    }};
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn parse(choice: &str) -> Result<Self, String> {
+        match choice {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            c => Err(format!("Invalid color: {}. Use auto, always, or never.", c)),
+        }
+    }
+}
+
+// Quiet only prints errors and warnings.
+// Normal hides the files renamed in large batches.
+// Verbose prints every file.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Quiet = 0,
+    Normal = 1,
+    Verbose = 2,
+}
+
+pub fn init(color: ColorChoice, level: Level) {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let dumb = env::var_os("TERM").is_some_and(|t| t == "dumb");
+    let colored = use_color(color, no_color || dumb, io::stdout().is_terminal());
+    COLOR.store(colored, Ordering::Relaxed);
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+// Auto colors terminals, unless NO_COLOR is set.
+// Always and never override NO_COLOR.
+fn use_color(choice: ColorChoice, no_color: bool, is_terminal: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => is_terminal && !no_color,
+    }
+}
+
+pub fn is_quiet() -> bool {
+    LEVEL.load(Ordering::Relaxed) == Level::Quiet as u8
+}
+
+// Whether each file of the batch gets its own line.
+pub fn show_files(total: usize) -> bool {
+    match LEVEL.load(Ordering::Relaxed) {
+        l if l == Level::Verbose as u8 => true,
+        l if l == Level::Normal as u8 => total < LARGE_BATCH,
+        _ => false,
+    }
+}
+
+pub fn red(text: impl Display) -> String {
+    paint("0;41", text)
+}
+
+pub fn yellow(text: impl Display) -> String {
+    paint("0;33", text)
+}
+
+pub fn cyan(text: impl Display) -> String {
+    paint("0;36", text)
+}
+
+fn paint(code: &str, text: impl Display) -> String {
+    match COLOR.load(Ordering::Relaxed) {
+        true => format!("\x1b[{}m{}\x1b[0m", code, text),
+        false => text.to_string(),
+    }
+}
+
+// Clears the progress bar, so the next line starts clean.
+// The bar is drawn again with the next file.
+pub fn clear_progress() {
+    if PROGRESS_SHOWN.swap(false, Ordering::Relaxed) {
+        print!("\r{:width$}\r", "", width = BAR_WIDTH + 30);
+        io::stdout().flush().ok();
+    }
+}
+
+// A progress bar for large batches at the normal level.
+// It is only drawn on terminals, so logs of cluster jobs stay clean.
+pub struct Progress {
+    total: usize,
+    counts: usize,
+    filled: usize,
+    shown: bool,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Self {
+        let level = LEVEL.load(Ordering::Relaxed);
+        Self {
+            total,
+            counts: 0,
+            filled: 0,
+            shown: level == Level::Normal as u8 && total >= LARGE_BATCH && io::stdout().is_terminal(),
+        }
+    }
+
+    // Redrawn only when the bar grows, at the last file,
+    // or after a message cleared it.
+    pub fn tick(&mut self) {
+        self.counts += 1;
+        if !self.shown {
+            return;
+        }
+
+        let filled = get_filled(self.counts, self.total);
+        let redraw = filled != self.filled || self.counts == self.total || !PROGRESS_SHOWN.load(Ordering::Relaxed);
+        if !redraw {
+            return;
+        }
+
+        self.filled = filled;
+        print!("\r[{}{}] {}/{}", "#".repeat(filled), "-".repeat(BAR_WIDTH - filled), self.counts, self.total);
+        io::stdout().flush().ok();
+        PROGRESS_SHOWN.store(true, Ordering::Relaxed);
+    }
+
+    pub fn finish(&self) {
+        if self.shown && PROGRESS_SHOWN.swap(false, Ordering::Relaxed) {
+            println!();
+        }
+    }
+}
+
+fn get_filled(counts: usize, total: usize) -> usize {
+    match total {
+        0 => BAR_WIDTH,
+        total => counts.min(total) * BAR_WIDTH / total,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn use_color_test() {
+        assert!(use_color(ColorChoice::Auto, false, true));
+        assert!(!use_color(ColorChoice::Auto, true, true));
+        assert!(!use_color(ColorChoice::Auto, false, false));
+        assert!(use_color(ColorChoice::Always, true, false));
+        assert!(!use_color(ColorChoice::Never, false, true));
+    }
+
+    #[test]
+    fn parse_color_test() {
+        assert_eq!(Ok(ColorChoice::Never), ColorChoice::parse("never"));
+        assert!(ColorChoice::parse("yes").is_err());
+    }
+
+    #[test]
+    fn get_filled_test() {
+        assert_eq!(0, get_filled(1, 100));
+        assert_eq!(20, get_filled(50, 100));
+        assert_eq!(BAR_WIDTH, get_filled(100, 100));
+    }
+}
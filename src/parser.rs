@@ -1,152 +1,562 @@
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{self, BufReader, Error};
-use std::io::prelude::*;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use csv::{ReaderBuilder, StringRecord, Trim};
+
+use crate::checker::{self, Checked};
+use crate::companion;
+use crate::error::Error;
+use crate::extension;
+use crate::output;
+use crate::plan::RenamePlan;
+use crate::portable::FoldedNames;
+use crate::policy::{self, RunOpts};
+use crate::report;
+use crate::spreadsheet;
+use crate::template::{Row, Template};
+
+// Options for reading the rename table.
+// Columns are given by the header name or by number, starting from 1.
+// Without them, the first column is the original names
+// and the second column is the new names.
+// Without a delimiter, we guess it from the file.
+// Sheet and header row only apply to spreadsheets.
+// Without them, we use the first sheet and its first row.
+// With a destination root, the new names are full paths relative to it,
+// so files can be moved to other directories.
+// Otherwise, the files stay in their directories.
+// With a template, the new names are built from the columns
+// instead of being read from one.
+#[derive(Debug, Default)]
+pub struct InputOpts {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub delimiter: Option<u8>,
+    pub sheet: Option<String>,
+    pub header_row: Option<usize>,
+    pub dest_root: Option<PathBuf>,
+    pub template: Option<Template>,
+}
+
+// The rename table as read from the input file.
+// Each row comes with its line number,
+// or the row number for spreadsheets.
+#[derive(Debug)]
+pub struct Table {
+    pub headers: StringRecord,
+    pub rows: Vec<(usize, StringRecord)>,
+}
+
+// Accepts the delimiter character or its name.
+pub fn parse_delimiter(delim: &str) -> Result<u8, String> {
+    match delim {
+        "tab" | "\\t" | "\t" => Ok(b'\t'),
+        "comma" => Ok(b','),
+        "semicolon" => Ok(b';'),
+        "pipe" => Ok(b'|'),
+        d if d.len() == 1 && d.is_ascii() => Ok(d.as_bytes()[0]),
+        d => Err(format!("Invalid delimiter: {}. Use a single character or tab.", d)),
+    }
+}
+
+// Tsv and txt are tab delimited.
+// Otherwise, we sniff the header for the most common delimiter.
+// Falls back to comma.
+fn detect_delimiter(path: &str, contents: &[u8]) -> u8 {
+    let ext = Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+
+    match ext.as_deref() {
+        Some("tsv") | Some("txt") => b'\t',
+        _ => {
+            let header = contents.split(|&c| c == b'\n').next().unwrap_or_default();
+            [b',', b';', b'\t', b'|'].iter()
+                .map(|&d| (header.iter().filter(|&&c| c == d).count(), d))
+                .filter(|(counts, _)| *counts > 0)
+                .max_by_key(|(counts, d)| (*counts, *d == b','))
+                .map_or(b',', |(_, d)| d)
+        }
+    }
+}
+
+// Columns resolved against the header.
+struct Columns {
+    from: usize,
+    to: usize,
+    from_name: String,
+    to_name: String,
+    selected: bool,
+}
+
+impl Columns {
+    fn new(headers: &StringRecord, opts: &InputOpts) -> Result<Self, Error> {
+        let (from, from_name) = find_column(headers, &opts.from, 0)?;
+        let (to, to_name) = find_column(headers, &opts.to, 1)?;
+
+        Ok(Self {
+            from,
+            to,
+            from_name,
+            to_name,
+            selected: opts.from.is_some() || opts.to.is_some(),
+        })
+    }
+}
+
+// Header names take precedence over column numbers.
+// Numbers count from 1. Shared with the template placeholders.
+pub fn get_column_index(headers: &StringRecord, col: &str) -> Option<usize> {
+    headers.iter()
+        .position(|h| h == col)
+        .or_else(|| match col.parse::<usize>() {
+            Ok(num) if num > 0 && num <= headers.len() => Some(num - 1),
+            _ => None,
+        })
+}
 
-use crate::checker;
-use std::process;
+fn find_column(
+    headers: &StringRecord,
+    col: &Option<String>,
+    default: usize
+) -> Result<(usize, String), Error> {
+    let idx = match col {
+        None => default,
+        Some(col) => get_column_index(headers, col)
+            .ok_or_else(|| Error::ColumnNotFound(col.to_string()))?,
+    };
+
+    let name = headers.get(idx)
+        .filter(|h| !h.is_empty())
+        .map_or_else(|| format!("COLUMN {}", idx + 1), String::from);
+
+    Ok((idx, name))
+}
 
-pub fn parse_csv(path: &str, dryrun: bool) -> HashMap<PathBuf, PathBuf> {
-    let file = File::open(path).unwrap();
-    let buff = BufReader::new(&file);
+// Reads the rename table from a csv or a spreadsheet
+// and checks it before renaming.
+// Dry run fails if the check finds errors.
+// Otherwise, the user decides whether to carry on,
+// unless it is answered up front with --yes.
+pub fn parse_input(
+    path: &str,
+    opts: &InputOpts,
+    run: &RunOpts
+) -> Result<(RenamePlan, Vec<Checked>), Error> {
+    let mut plan = read_input(path, opts)?;
+    if run.companions {
+        companion::add_companions(&mut plan)?;
+    }
+    let checks = check_plan(path, &plan, run)?;
 
-    let mut filenames: HashMap<PathBuf, PathBuf> = HashMap::new();
-    let mut lcounts = 1; // Indexing line.
+    Ok((plan, checks))
+}
+
+// Checks the files in the plan, whichever way it was built.
+// Returns the check of each row for the report.
+// The report of the check is written for dry runs,
+// and for runs that stop at the check.
+pub fn check_plan(input: &str, plan: &RenamePlan, run: &RunOpts) -> Result<Vec<Checked>, Error> {
     let mut errors = 0;
-    println!("Checking csv input...");
-    buff.lines()
-        .filter_map(|ok| ok.ok())
+    let portable = run.portable.then(|| FoldedNames::new(plan));
+    let checks: Vec<Checked> = plan.entries()
+        .iter()
+        .map(|e| checker::check_input_errors(&e.origin, &e.destination, plan, run, portable.as_ref(), &mut errors))
+        .collect();
+
+    info!("\nEntries found: {}", plan.len());
+    let checked = check_input(&errors, run);
+    if run.dryrun || checked.is_err() {
+        report::report_checks(input, plan, &checks, run)?;
+    }
+
+    checked.map(|_| checks)
+}
+
+// Problems in the rows are collected, so all of them
+// are reported at once.
+fn read_input(path: &str, opts: &InputOpts) -> Result<RenamePlan, Error> {
+    let table = if spreadsheet::is_spreadsheet(path) {
+        spreadsheet::read_spreadsheet(path, opts)?
+    } else {
+        read_csv(path, opts)?
+    };
+    let cols = Columns::new(&table.headers, opts)?;
+    if let Some(template) = &opts.template {
+        template.check_placeholders(&table.headers)?;
+    }
+
+    let mut plan = RenamePlan::new();
+    let mut problems = Vec::new();
+    info!("Checking input...");
+    table.rows
+        .iter()
         .enumerate()
-        .for_each(|(i, recs)| {
-            match i {
-                0 => lcounts+=1, // Ignoring the header
-                _ => { 
-                    let files = split_csv_lines(&recs, &lcounts);
-                    let old_names = PathBuf::from(&files[0]);
-                    let mut new_names = PathBuf::from(&files[1]);
-                    new_names = construct_new_names(&old_names, &new_names);
-                    checker::check_input_errors(&old_names, &new_names, &mut errors);
-                    filenames.insert(old_names, new_names);
-                    lcounts += 1;
+        .for_each(|(idx, (lcounts, recs))| {
+            let res = match &opts.template {
+                Some(template) => {
+                    parse_template(template, &table.headers, recs, &cols, opts, (idx + 1, lcounts), &mut plan)
                 }
+                None => parse_records(recs, &cols, opts, lcounts, &mut plan),
+            };
+            if let Err(e) = res {
+                problems.push(e);
             }
-            
         });
 
-    println!("\nEntries found: {}", lcounts - 2); // exclude header.
-    check_input(&errors, dryrun);
+    if !problems.is_empty() {
+        return Err(Error::Table(problems));
+    }
 
-    filenames
+    Ok(plan)
 }
 
-fn split_csv_lines(lines: &str, lcounts: &u32) -> Vec<String> {
-    let files: Vec<String> = lines.split(',')
-        .map(|e| e.trim().to_string())
-        .collect();
-
-    let cols = files.len();
+fn parse_records(
+    recs: &StringRecord,
+    cols: &Columns,
+    opts: &InputOpts,
+    lcounts: &usize,
+    plan: &mut RenamePlan
+) -> Result<(), Error> {
+    let (old_names, new_names) = split_csv_records(recs, cols, lcounts)?;
+    let old_names = PathBuf::from(old_names);
+    let new_names = construct_new_names(
+        &old_names,
+        Path::new(new_names),
+        opts.dest_root.as_deref(),
+        lcounts
+    )?;
+    plan.push(*lcounts, old_names, new_names)
+}
 
-    if cols < 2 || files[1].is_empty() {
-        panic!("INVALID CSV INPUT! ONLY ONE COLUMN FOUND IN LINE {}.", lcounts);
-    } 
-    
-    if cols > 2 {
-        println!("\x1b[0;33mLINE {} HAS MORE THAN TWO COLUMNS.\
-            ASSUMING THE FIRST TWO ARE THE FILENAMES.\x1b[0m", lcounts);
+// Only the original names are read from the table.
+// Counts is the row counter for the template, starting from 1.
+fn parse_template(
+    template: &Template,
+    headers: &StringRecord,
+    recs: &StringRecord,
+    cols: &Columns,
+    opts: &InputOpts,
+    (counts, lcounts): (usize, &usize),
+    plan: &mut RenamePlan
+) -> Result<(), Error> {
+    let old_names = recs.get(cols.from).unwrap_or_default();
+    if old_names.is_empty() {
+        return Err(Error::InvalidRow { line: *lcounts, msg: format!("EMPTY {}", cols.from_name) });
     }
-    
-    files   
+
+    let old_names = PathBuf::from(old_names);
+    let row = Row { headers, recs, old_names: &old_names, counts, lcounts: *lcounts };
+    let prop_names = template.fill(&row)?;
+    let new_names = construct_new_names(
+        &old_names,
+        Path::new(&prop_names),
+        opts.dest_root.as_deref(),
+        lcounts
+    )?;
+    plan.push(*lcounts, old_names, new_names)
 }
 
-fn construct_new_names(old_names: &PathBuf, prop_names: &PathBuf) -> PathBuf {
+// Reads the csv following RFC 4180.
+// Fields may be quoted, contain commas, escaped quotes, and line breaks.
+// The csv reader also takes care of CRLF line endings and UTF-8 BOM.
+// Line numbers point to the line where the record starts.
+fn read_csv(path: &str, opts: &InputOpts) -> Result<Table, Error> {
+    let contents = fs::read(path).map_err(Error::io(Path::new(path)))?;
+    let delimiter = opts.delimiter
+        .unwrap_or_else(|| detect_delimiter(path, &contents));
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .trim(Trim::All)
+        .from_reader(&contents[..]);
+
+    let invalid_csv = |e: csv::Error| Error::InvalidInput {
+        path: PathBuf::from(path),
+        msg: e.to_string(),
+    };
+    let headers = reader.headers().map_err(invalid_csv)?.clone();
+
+    let rows = reader.records()
+        .map(|recs| {
+            let recs = recs.map_err(invalid_csv)?;
+            let lcounts = recs.position().map_or(0, |pos| count_lines(&contents, pos.byte()));
+            Ok((lcounts, recs))
+        })
+        .collect::<Result<_, Error>>()?;
+
+    Ok(Table { headers, rows })
+}
 
-    let parent_path = old_names.parent().unwrap();
-    let filenames = prop_names.file_name().unwrap();
-    let mut new_names = parent_path.join(filenames);
+// The csv reader miscounts lines on CRLF files.
+// We count the line breaks before the record instead.
+// On CRLF files, the record position points to the '\n'
+// of the previous line, hence we include it.
+fn count_lines(contents: &[u8], byte: u64) -> usize {
+    let end = (byte as usize + 1).min(contents.len());
+    contents[..end].iter().filter(|&&c| c == b'\n').count() + 1
+}
 
-    match_extension(old_names, &mut new_names)
-        .expect("Can't match file extension");
+fn split_csv_records<'a>(
+    recs: &'a StringRecord,
+    cols: &Columns,
+    lcounts: &usize
+) -> Result<(&'a str, &'a str), Error> {
+    let old_names = recs.get(cols.from).unwrap_or_default();
+    let new_names = recs.get(cols.to).unwrap_or_default();
+    let filled = recs.iter().filter(|f| !f.is_empty()).count();
+    let invalid_row = |msg: String| Error::InvalidRow { line: *lcounts, msg };
+
+    if filled < 2 {
+        return Err(invalid_row(String::from("ONLY ONE COLUMN FOUND")));
+    }
+
+    if old_names.is_empty() {
+        return Err(invalid_row(format!("EMPTY {}", cols.from_name)));
+    }
+
+    if new_names.is_empty() {
+        return Err(invalid_row(format!("EMPTY {}", cols.to_name)));
+    }
+
+    if !cols.selected && recs.len() > 2 {
+        warn!("{}", output::yellow(format!("LINE {} HAS MORE THAN TWO COLUMNS.\
+            ASSUMING THE FIRST TWO ARE THE FILENAMES.", lcounts)));
+    }
 
-    new_names
+    Ok((old_names, new_names))
 }
 
-fn match_extension(old_name: &PathBuf, new_names: &mut PathBuf) -> Result<(), Error>{
-    let ext = old_name.extension().unwrap();
-    if ext != new_names.extension().unwrap() {
-        new_names.set_extension(ext);
+// Without a destination root, only the file name of the proposed name is used.
+// Absolute proposed names are kept as they are in move mode.
+fn construct_new_names(
+    old_names: &Path,
+    prop_names: &Path,
+    dest_root: Option<&Path>,
+    lcounts: &usize
+) -> Result<PathBuf, Error> {
+    let no_file_name = |path: &Path| Error::InvalidRow {
+        line: *lcounts,
+        msg: format!("NO FILE NAME IN {:?}", path),
+    };
+    old_names.file_name().ok_or_else(|| no_file_name(old_names))?;
+    let filenames = prop_names.file_name().ok_or_else(|| no_file_name(prop_names))?;
+
+    let mut new_names = match dest_root {
+        Some(root) => root.join(prop_names),
+        None => old_names.parent().unwrap_or_else(|| Path::new("")).join(filenames),
+    };
+    match_extension(old_names, &mut new_names)?;
+
+    Ok(new_names)
+}
+
+// Files without extension, such as README, keep the proposed name as it is.
+// Compound extensions are compared as a whole,
+// so new.fq.gz for old.fastq.gz becomes new.fastq.gz.
+// A change of case in the extension is kept.
+fn match_extension(old_name: &Path, new_names: &mut PathBuf) -> Result<(), Error> {
+    let ext = extension::get_extension(old_name);
+    if !ext.is_empty() {
+        let (stem, new_ext) = extension::split_file_names(new_names);
+        if !ext.eq_ignore_ascii_case(&new_ext) {
+            new_names.set_file_name(format!("{}{}", stem, ext));
+        }
     }
 
     Ok(())
 }
 
-fn check_input(errors: &u32, dryrun: bool) {
+fn check_input(errors: &u32, run: &RunOpts) -> Result<(), Error> {
     if *errors > 0 {
         checker::display_errors(errors);
-        if !dryrun {
-            get_user_input_err().unwrap();
+        if run.dryrun {
+            return Err(Error::Validation(*errors));
         }
+        get_user_input_err(errors, run.yes)?;
     }
+
+    Ok(())
 }
 
-fn get_user_input_err() -> Result<(), Error> {
-    println!("\nWould you like to continue: [y]es/[n]o? ");
-    loop {
-        let input = io::stdin()
-            .bytes()
-            .next()
-            .and_then(|ok| ok.ok())
-            .map(|k| k as u8)
-            .unwrap();
-        
-        match input {
-            b'y' => break,
-            b'n' => process::exit(1),
-            _ => println!("Incorrect input! Please, try again...")
-        };
+fn get_user_input_err(errors: &u32, yes: bool) -> Result<(), Error> {
+    if yes {
+        info!("\nContinuing despite the errors...");
+        return Ok(());
     }
 
-    Ok(())
+    if !policy::is_interactive() {
+        warn!("\nStdin is not a terminal. Use --yes to continue despite the errors.");
+        return Err(Error::Validation(*errors));
+    }
+
+    match policy::ask("\nWould you like to continue: [y]es/[n]o? ", b"yn")? {
+        b'y' => Ok(()),
+        _ => Err(Error::Validation(*errors)),
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    use std::env;
+    use std::fs;
+
+    fn default_columns() -> Columns {
+        let headers = StringRecord::from(vec!["old_names", "new_names"]);
+        Columns::new(&headers, &InputOpts::default()).unwrap()
+    }
+
     #[test]
-    fn split_csv_lines_test() {
-        let lines = String::from("./test/old_names.fastq.gz,./test/new_names.fastq.gz");
+    fn split_csv_records_test() {
+        let recs = StringRecord::from(vec!["./test/old_names.fastq.gz", "./test/new_names.fastq.gz"]);
         let lcounts = 1;
-        let res = split_csv_lines(&lines, &lcounts);
+        let res = split_csv_records(&recs, &default_columns(), &lcounts).unwrap();
 
-        assert_eq!(2, res.len());
+        assert_eq!(("./test/old_names.fastq.gz", "./test/new_names.fastq.gz"), res);
     }
 
     #[test]
     fn multicols_csv_split_test() {
-        let lines = String::from("./test/old_names.fastq.gz,\
-            ./test/new_names.fastq.gz,\
-            ./test/new_names.fastq.gz");
+        let recs = StringRecord::from(vec![
+            "./test/old_names.fastq.gz",
+            "./test/new_names.fastq.gz",
+            "./test/other_names.fastq.gz"
+        ]);
         let lcounts = 1;
-        let res = split_csv_lines(&lines, &lcounts);
+        let res = split_csv_records(&recs, &default_columns(), &lcounts).unwrap();
         
-        assert_eq!(3, res.len());
+        assert_eq!(("./test/old_names.fastq.gz", "./test/new_names.fastq.gz"), res);
     }
 
     #[test]
-    #[should_panic]
-    fn split_csv_empty_col_panic_test() {
-        let empty_cols = String::from("./test/old_names.fastq.gz,");
+    fn split_csv_empty_col_err_test() {
+        let empty_cols = StringRecord::from(vec!["./test/old_names.fastq.gz", ""]);
         let lcols = 1;
-        split_csv_lines(&empty_cols, &lcols);
+        assert!(split_csv_records(&empty_cols, &default_columns(), &lcols).is_err());
     }
 
     #[test]
-    #[should_panic]
-    fn split_csv_one_cols_panic_test() {
-        let one_col = String::from("./test/old_names.fastq.gz");
+    fn split_csv_one_cols_err_test() {
+        let one_col = StringRecord::from(vec!["./test/old_names.fastq.gz"]);
         let lcols = 1;
-        split_csv_lines(&one_col, &lcols);
+        assert!(split_csv_records(&one_col, &default_columns(), &lcols).is_err());
+    }
+
+    #[test]
+    fn split_csv_empty_selected_col_err_test() {
+        let recs = StringRecord::from(vec!["./test/old_names.fastq.gz", "new.fastq.gz", ""]);
+        let headers = StringRecord::from(vec!["old_names", "new_names", "species"]);
+        let opts = InputOpts { to: Some(String::from("species")), ..InputOpts::default() };
+        let cols = Columns::new(&headers, &opts).unwrap();
+        let err = split_csv_records(&recs, &cols, &4).unwrap_err();
+
+        assert_eq!("INVALID INPUT! EMPTY species IN LINE 4.", err.to_string());
+    }
+
+    #[test]
+    fn find_column_test() {
+        let headers = StringRecord::from(vec!["full_path", "new_names", "3"]);
+
+        let find = |col: &str| find_column(&headers, &Some(String::from(col)), 0).unwrap().0;
+
+        assert_eq!((0, String::from("full_path")), find_column(&headers, &None, 0).unwrap());
+        assert_eq!(1, find("new_names"));
+        assert_eq!(1, find("2"));
+        assert_eq!(2, find("3"));
+    }
+
+    #[test]
+    fn find_column_err_test() {
+        let headers = StringRecord::from(vec!["full_path", "new_names"]);
+        let err = find_column(&headers, &Some(String::from("sample")), 0).unwrap_err();
+
+        assert_eq!("COLUMN sample NOT FOUND IN THE HEADER.", err.to_string());
+    }
+
+    #[test]
+    fn parse_delimiter_test() {
+        assert_eq!(Ok(b'\t'), parse_delimiter("tab"));
+        assert_eq!(Ok(b'\t'), parse_delimiter("\t"));
+        assert_eq!(Ok(b';'), parse_delimiter(";"));
+        assert!(parse_delimiter(";;").is_err());
+    }
+
+    #[test]
+    fn detect_delimiter_test() {
+        let header = b"old_names;new_names;id,no\nx;y;z\n";
+
+        assert_eq!(b'\t', detect_delimiter("data/input.tsv", header));
+        assert_eq!(b'\t', detect_delimiter("data/input.TXT", header));
+        assert_eq!(b';', detect_delimiter("data/input.csv", header));
+        assert_eq!(b',', detect_delimiter("data/input.csv", b"old_names\n"));
+        assert_eq!(b',', detect_delimiter("data/input.csv", b"old;new,id|no\n"));
+    }
+
+    #[test]
+    fn parse_semicolon_csv_test() {
+        let input = write_test_csv("semicolon.csv",
+            "old_names;new_names\r\n\"data/old;1.fq.gz\";new_1.fq.gz\r\n");
+        let plan = read_input(&input, &InputOpts::default()).unwrap();
+
+        assert_eq!(PathBuf::from("data/old;1.fq.gz"), plan.entries()[0].origin);
+        assert_eq!(PathBuf::from("data/new_1.fq.gz"), plan.entries()[0].destination);
+    }
+
+    #[test]
+    fn parse_selected_columns_test() {
+        let input = "test_files/multicols_input.csv";
+        let opts = InputOpts {
+            from: Some(String::from("old_names")),
+            to: Some(String::from("another_cols")),
+            ..InputOpts::default()
+        };
+        let plan = read_input(input, &opts).unwrap();
+
+        assert_eq!(PathBuf::from("test_files/valid2.fastq.gzip"), plan.entries()[0].origin);
+        assert_eq!(PathBuf::from("test_files/valid2.fastq.gzip"), plan.entries()[0].destination);
+    }
+
+    fn write_test_csv(fname: &str, contents: &str) -> String {
+        let dir = env::temp_dir().join("renamer_parser_test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(fname);
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    // Excel style csv: BOM, CRLF, quoted fields with commas,
+    // escaped quotes, and a line break.
+    fn write_excel_csv(fname: &str, rows: &str) -> String {
+        let contents = format!("\u{feff}\"old_names\",\"new_names\"\r\n{}", rows);
+        write_test_csv(fname, &contents)
+    }
+
+    #[test]
+    fn parse_quoted_csv_test() {
+        let input = write_excel_csv("quoted.csv", "\
+            \"data/old, 1.fq.gz\",\"new \"\"1\"\".fq.gz\"\r\n\
+            \"data/old\n2.fq.gz\",new_2.fq.gz\r\n\
+            data/old_3.fq.gz,new_3.fq.gz\r\n");
+        let plan = read_input(&input, &InputOpts::default()).unwrap();
+        let entries = plan.entries();
+
+        assert_eq!(3, plan.len());
+        assert_eq!(PathBuf::from("data/old, 1.fq.gz"), entries[0].origin);
+        assert_eq!(PathBuf::from("data/new \"1\".fq.gz"), entries[0].destination);
+        assert_eq!(PathBuf::from("data/old\n2.fq.gz"), entries[1].origin);
+        assert_eq!(vec![2, 3, 5], entries.iter().map(|e| e.line).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn parse_quoted_csv_line_err_test() {
+        let input = write_excel_csv("quoted_invalid.csv", "\
+            \"data/old\n1.fq.gz\",new_1.fq.gz\r\n\
+            data/old_2.fq.gz\r\n");
+        let err = read_input(&input, &InputOpts::default()).unwrap_err();
+
+        assert!(err.to_string().ends_with("INVALID INPUT! ONLY ONE COLUMN FOUND IN LINE 4."));
     }
 
     #[test]
@@ -155,11 +565,13 @@ mod test {
         let old = PathBuf::from("test_files/valid.fastq.gz");
         let new = PathBuf::from("test_files/valid_new.fastq.gz");
 
-        let filenames = parse_csv(&input, true);
+        let plan = read_input(input, &InputOpts::default()).unwrap();
 
-        for (old_names, new_names) in filenames {
-            assert_eq!(old, old_names);
-            assert_eq!(new, new_names);
+        assert_eq!(1, plan.len());
+        for e in plan.entries() {
+            assert_eq!(2, e.line);
+            assert_eq!(old, e.origin);
+            assert_eq!(new, e.destination);
         }
     }
 
@@ -169,26 +581,109 @@ mod test {
         let old = PathBuf::from("test_files/valid2.fastq.gzip");
         let new = PathBuf::from("test_files/valid_new2.fastq.gzip");
 
-        let filenames = parse_csv(&input, true);
+        let plan = read_input(input, &InputOpts::default()).unwrap();
 
-        for (old_names, new_names) in filenames {
-            assert_eq!(old, old_names);
-            assert_eq!(new, new_names);
+        for e in plan.entries() {
+            assert_eq!(old, e.origin);
+            assert_eq!(new, e.destination);
         }
     }
 
     #[test]
-    #[should_panic]
-    fn parse_csv_panic_test() {
+    fn parse_dry_run_err_test() {
+        let input = write_test_csv("missing.csv", "old_names,new_names\n\
+            test_files/valid.fastq.gz,valid_new.fastq.gz\n\
+            test_files/missing.fastq.gz,missing_new.fastq.gz\n");
+        let dry = RunOpts { dryrun: true, ..RunOpts::default() };
+        let err = parse_input(&input, &InputOpts::default(), &dry).unwrap_err();
+
+        assert!(matches!(err, Error::Validation(1)));
+        assert!(parse_input("test_files/input.csv", &InputOpts::default(), &dry).is_ok());
+
+        let yes = RunOpts { yes: true, ..RunOpts::default() };
+        assert_eq!(2, parse_input(&input, &InputOpts::default(), &yes).unwrap().0.len());
+    }
+
+    #[test]
+    fn parse_csv_err_test() {
         let input = "test_files/invalid_input.csv";
-        parse_csv(&input, true);
+        assert!(read_input(input, &InputOpts::default()).is_err());
     }
 
     #[test]
-    #[should_panic(expected="INVALID CSV INPUT! ONLY ONE COLUMN FOUND IN LINE 3.")]
-    fn parse_csv_panic_message_test() {
+    fn parse_csv_err_message_test() {
         let input = "test_files/invalid_input.csv";
-        parse_csv(&input, true);
+        let err = read_input(input, &InputOpts::default()).unwrap_err();
+
+        assert_eq!("ERRORS FOUND IN THE INPUT: 1\n\
+            INVALID INPUT! ONLY ONE COLUMN FOUND IN LINE 3.",
+            err.to_string()
+        );
+    }
+
+    // Every problem in the table is reported, not only the first one.
+    #[test]
+    fn parse_csv_all_errors_test() {
+        let input = write_test_csv("all_errors.csv", "old_names,new_names\n\
+            data/a.fq.gz,x.fq.gz\n\
+            data/b.fq.gz\n\
+            data/a.fq.gz,y.fq.gz\n\
+            data/c.fq.gz,..\n");
+        match read_input(&input, &InputOpts::default()) {
+            Err(Error::Table(errors)) => {
+                let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                assert_eq!(3, lines.len());
+                assert!(lines[0].ends_with("IN LINE 3."));
+                assert!(lines[1].starts_with("DUPLICATE SOURCE"));
+                assert_eq!("INVALID INPUT! NO FILE NAME IN \"..\" IN LINE 5.", lines[2]);
+            }
+            _ => panic!("Expected table errors"),
+        }
+    }
+
+    #[test]
+    fn parse_template_test() {
+        let input = write_test_csv("template.csv", "path,species,voucher\n\
+            raw/a.fastq.gz,Bunomys_andrewsi,LSUMZ_1\n\
+            raw/b.fq.gz,Bunomys_chrysocomus,\n\
+            raw/c.fq.gz,Rattus_rattus,LSUMZ_3\n");
+        let opts = InputOpts {
+            template: Some(Template::parse("{species}_{voucher}_{row:2}.{ext}").unwrap()),
+            ..InputOpts::default()
+        };
+
+        match read_input(&input, &opts) {
+            Err(Error::Table(errors)) => {
+                assert_eq!(1, errors.len());
+                assert!(errors[0].to_string().ends_with("IN LINE 3."));
+            }
+            _ => panic!("Expected table errors"),
+        }
+
+        let opts = InputOpts {
+            template: Some(Template::parse("{species}_{row}.{ext}").unwrap()),
+            ..InputOpts::default()
+        };
+        let plan = read_input(&input, &opts).unwrap();
+        assert_eq!(PathBuf::from("raw/Bunomys_andrewsi_1.fastq.gz"), plan.entries()[0].destination);
+        assert_eq!(PathBuf::from("raw/Rattus_rattus_3.fq.gz"), plan.entries()[2].destination);
+
+        let opts = InputOpts {
+            template: Some(Template::parse("{genus}.{ext}").unwrap()),
+            ..InputOpts::default()
+        };
+        assert!(matches!(read_input(&input, &opts), Err(Error::Placeholder(_))));
+    }
+
+    #[test]
+    fn parse_csv_duplicate_err_test() {
+        let input = write_test_csv("duplicate_input.csv", "old_names,new_names\n\
+            test_files/valid.fastq.gz,valid_new.fastq.gz\n\
+            test_files/valid.fastq.gz,valid_other.fastq.gz\n");
+        let err = read_input(&input, &InputOpts::default()).unwrap_err();
+
+        assert!(err.to_string().ends_with("DUPLICATE SOURCE \"test_files/valid.fastq.gz\" \
+            IN LINE 3. ALREADY LISTED IN LINE 2."));
     }
 
     #[test]
@@ -199,8 +694,18 @@ mod test {
 
         let new_names = PathBuf::from("data/new.fq.gz");
 
-        assert_eq!(new_names, construct_new_names(&old_name, &prop_name));
-        assert_eq!(new_names, construct_new_names(&old_name, &prop_path));
+        assert_eq!(new_names, construct_new_names(&old_name, &prop_name, None, &2).unwrap());
+        assert_eq!(new_names, construct_new_names(&old_name, &prop_path, None, &2).unwrap());
+    }
+
+    #[test]
+    fn construct_path_no_ext_test() {
+        let old_name = PathBuf::from("data/README");
+        let prop_name = PathBuf::from("README.md");
+        let no_parent = PathBuf::from("README");
+
+        assert_eq!(PathBuf::from("data/README.md"), construct_new_names(&old_name, &prop_name, None, &2).unwrap());
+        assert_eq!(PathBuf::from("README.md"), construct_new_names(&no_parent, &prop_name, None, &2).unwrap());
     }
 
     #[test]
@@ -210,7 +715,23 @@ mod test {
 
         let new_names = PathBuf::from("data/new.fq.gzip");
 
-        assert_eq!(new_names, construct_new_names(&old_name, &prop_name));
+        assert_eq!(new_names, construct_new_names(&old_name, &prop_name, None, &2).unwrap());
+    }
+
+    #[test]
+    fn construct_path_dest_root_test() {
+        let old_name = PathBuf::from("raw/old.fq.gz");
+        let prop_name = PathBuf::from("project_a/new.fq.gz");
+        let root = Path::new("data");
+
+        assert_eq!(
+            PathBuf::from("data/project_a/new.fq.gz"),
+            construct_new_names(&old_name, &prop_name, Some(root), &2).unwrap()
+        );
+        assert_eq!(
+            PathBuf::from("/tmp/new.fq.gz"),
+            construct_new_names(&old_name, Path::new("/tmp/new.fq.gz"), Some(root), &2).unwrap()
+        );
     }
 
     #[test]
@@ -224,6 +745,26 @@ mod test {
         assert_eq!(res,new_names);
     }
 
+    #[test]
+    fn match_compound_extension_test() {
+        let old_name = PathBuf::from("data/old.fastq.gz");
+        let mut new_names = PathBuf::from("data/new.fq.gz");
+        match_extension(&old_name, &mut new_names).unwrap();
+        assert_eq!(PathBuf::from("data/new.fastq.gz"), new_names);
+
+        let mut no_ext = PathBuf::from("data/new");
+        match_extension(&old_name, &mut no_ext).unwrap();
+        assert_eq!(PathBuf::from("data/new.fastq.gz"), no_ext);
+
+        let mut lower = PathBuf::from("data/new.fastq.gz");
+        match_extension(Path::new("data/OLD.FASTQ.GZ"), &mut lower).unwrap();
+        assert_eq!(PathBuf::from("data/new.fastq.gz"), lower);
+
+        let mut index = PathBuf::from("data/new.vcf.gz.tbi");
+        match_extension(Path::new("data/old.vcf.gz.tbi"), &mut index).unwrap();
+        assert_eq!(PathBuf::from("data/new.vcf.gz.tbi"), index);
+    }
+
     #[test]
     fn match_extension_ok_test() {
         let old_name = PathBuf::from("data/old.fq.gzip");
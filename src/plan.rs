@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::Error;
+
+// A row of the rename table.
+// Line is the line number in the input file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub line: usize,
+    pub origin: PathBuf,
+    pub destination: PathBuf,
+}
+
+// Keeps the renaming in the same order as the input file.
+// The origins and destinations are indexed by their line numbers
+// to catch duplicates across the whole batch.
+// Paths are stored without ./ components and with .. resolved,
+// so ./data/a.fq, data/../data/a.fq, and data/a.fq are the same file.
+// The indexes use absolute paths, so a relative path
+// and the absolute path of the same file match too.
+#[derive(Debug, Default)]
+pub struct RenamePlan {
+    entries: Vec<Entry>,
+    origins: HashMap<PathBuf, usize>,
+    destinations: HashMap<PathBuf, usize>,
+}
+
+impl RenamePlan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Builds the plan for files found in a directory, without an input file.
+    // Files are numbered in path order, in place of the line numbers.
+    // All problems with the new names are reported at once.
+    pub fn from_files<I>(files: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = (PathBuf, Result<PathBuf, Error>)>,
+    {
+        let mut plan = Self::new();
+        let problems: Vec<Error> = files.into_iter()
+            .enumerate()
+            .filter_map(|(idx, (old_names, new_names))| {
+                new_names.and_then(|new_names| plan.push(idx + 1, old_names, new_names)).err()
+            })
+            .collect();
+
+        if !problems.is_empty() {
+            return Err(Error::Table(problems));
+        }
+
+        Ok(plan)
+    }
+
+    pub fn push(&mut self, line: usize, origin: PathBuf, destination: PathBuf) -> Result<(), Error> {
+        let origin = normalize_path(&origin);
+        let destination = normalize_path(&destination);
+        if let Some(prev) = self.origins.get(&get_key(&origin)) {
+            return Err(Error::Duplicate { kind: "SOURCE", path: origin, line, prev: *prev });
+        }
+
+        // Rows with a duplicate destination still claim their origin,
+        // so a later row listing the same origin is reported too.
+        self.origins.insert(get_key(&origin), line);
+        if let Some(prev) = self.destinations.get(&get_key(&destination)) {
+            return Err(Error::Duplicate {
+                kind: "DESTINATION",
+                path: destination,
+                line,
+                prev: *prev
+            });
+        }
+
+        self.destinations.insert(get_key(&destination), line);
+        self.entries.push(Entry { line, origin, destination });
+
+        Ok(())
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn contains_origin(&self, path: &Path) -> bool {
+        self.origins.contains_key(&get_key(path))
+    }
+
+    // The line of the row renaming the file.
+    pub fn origin_line(&self, path: &Path) -> Option<usize> {
+        self.origins.get(&get_key(path)).copied()
+    }
+}
+
+// Resolves .. lexically. Leading .. components are kept.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+// The same key for every spelling of a file: absolute, with .. resolved.
+// Used wherever files in the batch are matched.
+pub fn get_key(path: &Path) -> PathBuf {
+    match path.is_absolute() {
+        true => normalize_path(path),
+        false => normalize_path(&env::current_dir().unwrap_or_default().join(path)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn plan_from_files_test() {
+        let files = vec![
+            (PathBuf::from("data/a.fq"), Ok(PathBuf::from("data/x.fq"))),
+            (PathBuf::from("data/b.fq"), Ok(PathBuf::from("data/y.fq"))),
+        ];
+        let plan = RenamePlan::from_files(files).unwrap();
+        assert_eq!(vec![1, 2], plan.entries().iter().map(|e| e.line).collect::<Vec<usize>>());
+
+        let files = vec![
+            (PathBuf::from("data/a.fq"), Ok(PathBuf::from("data/x.fq"))),
+            (PathBuf::from("data/b.fq"), Ok(PathBuf::from("data/x.fq"))),
+            (PathBuf::from("data/c.fq"), Err(Error::ColumnNotFound(String::from("c")))),
+        ];
+        let err = RenamePlan::from_files(files).unwrap_err();
+        assert!(matches!(err, Error::Table(ref e) if e.len() == 2));
+    }
+
+    #[test]
+    fn plan_keeps_order_test() {
+        let mut plan = RenamePlan::new();
+        plan.push(2, PathBuf::from("data/c.fq"), PathBuf::from("data/z.fq")).unwrap();
+        plan.push(3, PathBuf::from("data/a.fq"), PathBuf::from("data/x.fq")).unwrap();
+
+        let lines: Vec<usize> = plan.entries().iter().map(|e| e.line).collect();
+        assert_eq!(vec![2, 3], lines);
+        assert_eq!(PathBuf::from("data/c.fq"), plan.entries()[0].origin);
+        assert!(plan.contains_origin(Path::new("data/a.fq")));
+        assert!(!plan.contains_origin(Path::new("data/x.fq")));
+    }
+
+    #[test]
+    fn plan_duplicate_origin_test() {
+        let mut plan = RenamePlan::new();
+        plan.push(2, PathBuf::from("data/a.fq"), PathBuf::from("data/x.fq")).unwrap();
+        let err = plan.push(4, PathBuf::from("data/a.fq"), PathBuf::from("data/y.fq"))
+            .unwrap_err();
+
+        assert_eq!(
+            "DUPLICATE SOURCE \"data/a.fq\" IN LINE 4. ALREADY LISTED IN LINE 2.",
+            err.to_string()
+        );
+        assert_eq!(1, plan.len());
+    }
+
+    #[test]
+    fn plan_duplicate_destination_test() {
+        let mut plan = RenamePlan::new();
+        plan.push(2, PathBuf::from("data/a.fq"), PathBuf::from("data/x.fq")).unwrap();
+        let res = plan.push(3, PathBuf::from("data/b.fq"), PathBuf::from("data/x.fq"));
+        assert!(res.is_err());
+
+        let err = plan.push(4, PathBuf::from("./data/c.fq"), PathBuf::from("./data/x.fq"))
+            .unwrap_err();
+        assert_eq!(
+            "DUPLICATE DESTINATION \"data/x.fq\" IN LINE 4. ALREADY LISTED IN LINE 2.",
+            err.to_string()
+        );
+
+        let err = plan.push(5, PathBuf::from("data/c.fq"), PathBuf::from("data/z.fq"))
+            .unwrap_err();
+        assert_eq!(
+            "DUPLICATE SOURCE \"data/c.fq\" IN LINE 5. ALREADY LISTED IN LINE 4.",
+            err.to_string()
+        );
+        assert_eq!(1, plan.len());
+    }
+
+    #[test]
+    fn plan_parent_dir_test() {
+        let mut plan = RenamePlan::new();
+        plan.push(2, PathBuf::from("data/../data/a.fq"), PathBuf::from("data/b.fq")).unwrap();
+        assert_eq!(PathBuf::from("data/a.fq"), plan.entries()[0].origin);
+
+        let err = plan.push(3, PathBuf::from("data/a.fq"), PathBuf::from("data/c.fq")).unwrap_err();
+        assert!(matches!(err, Error::Duplicate { kind: "SOURCE", prev: 2, .. }));
+
+        let absolute = env::current_dir().unwrap().join("data/b.fq");
+        let err = plan.push(4, PathBuf::from("data/d.fq"), absolute).unwrap_err();
+        assert!(matches!(err, Error::Duplicate { kind: "DESTINATION", prev: 2, .. }));
+        assert!(plan.contains_origin(&env::current_dir().unwrap().join("data/a.fq")));
+
+        assert_eq!(PathBuf::from("../a.fq"), normalize_path(Path::new("./../data/../a.fq")));
+    }
+
+    #[test]
+    fn plan_origin_line_test() {
+        let mut plan = RenamePlan::new();
+        plan.push(2, PathBuf::from("data/a.fq"), PathBuf::from("data/b.fq")).unwrap();
+        plan.push(3, PathBuf::from("./data/b.fq"), PathBuf::from("data/c.fq")).unwrap();
+
+        assert_eq!(Some(3), plan.origin_line(Path::new("data/b.fq")));
+        assert_eq!(Some(2), plan.origin_line(Path::new("./data/a.fq")));
+        assert_eq!(None, plan.origin_line(Path::new("data/c.fq")));
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::plan::{self, RenamePlan};
+
+// A single renaming step.
+// Temporary steps move a file out of the way
+// to break a cycle in the batch (A -> B, B -> A).
+#[derive(Debug, PartialEq)]
+pub struct Step {
+    pub origin: PathBuf,
+    pub destination: PathBuf,
+    pub temporary: bool,
+}
+
+// Orders the renaming, so no file is renamed
+// to a name still held by another file in the batch.
+// Each old -> new pair is an edge in the dependency graph.
+// A step is ready when its destination is not the origin of
+// another pending step. The steps are sorted topologically in one pass.
+// When nothing is ready, the rest are cycles.
+// We break one by moving its origin to a temporary name.
+// Otherwise, ready steps follow the order of the input file.
+// Files are matched by their plan keys, so absolute and relative
+// paths to the same file are the same node.
+pub fn plan_renaming(plan: &RenamePlan) -> Vec<Step> {
+    let mut pending: Vec<(PathBuf, PathBuf)> = plan.entries()
+        .iter()
+        .filter(|e| plan::get_key(&e.origin) != plan::get_key(&e.destination))
+        .map(|e| (e.origin.to_path_buf(), e.destination.to_path_buf()))
+        .collect();
+
+    let origins: HashMap<PathBuf, usize> = pending.iter()
+        .enumerate()
+        .map(|(i, (old, _))| (plan::get_key(old), i))
+        .collect();
+    // The step holding the destination of each step.
+    let blockers: Vec<Option<usize>> = pending.iter()
+        .map(|(_, new)| origins.get(&plan::get_key(new)).copied())
+        .collect();
+    let mut waiting: Vec<Vec<usize>> = vec![Vec::new(); pending.len()];
+    blockers.iter()
+        .enumerate()
+        .for_each(|(i, blocker)| {
+            if let Some(j) = blocker {
+                waiting[*j].push(i);
+            }
+        });
+    let mut names: HashSet<PathBuf> = pending.iter()
+        .flat_map(|(old, new)| [old.to_path_buf(), new.to_path_buf()])
+        .collect();
+
+    let mut steps = Vec::with_capacity(pending.len());
+    let mut done = vec![false; pending.len()];
+    let mut ready: VecDeque<usize> = (0..pending.len())
+        .filter(|&i| blockers[i].is_none())
+        .collect();
+    let mut next = 0;
+    loop {
+        while let Some(i) = ready.pop_front() {
+            done[i] = true;
+            let (old, new) = &pending[i];
+            steps.push(Step {
+                origin: old.to_path_buf(),
+                destination: new.to_path_buf(),
+                temporary: false,
+            });
+            ready.extend(waiting[i].iter().copied());
+        }
+
+        while next < pending.len() && done[next] {
+            next += 1;
+        }
+        if next == pending.len() {
+            break;
+        }
+
+        let cycle = find_cycle(next, &blockers);
+        let temp = create_temp_names(&pending[cycle].0, &names);
+        names.insert(temp.to_path_buf());
+        steps.push(Step {
+            origin: pending[cycle].0.to_path_buf(),
+            destination: temp.to_path_buf(),
+            temporary: true,
+        });
+        pending[cycle].0 = temp;
+        ready.extend(waiting[cycle].drain(..));
+    }
+
+    steps
+}
+
+// Follows the blockers of a pending step until one repeats.
+// The repeated step is on a cycle.
+fn find_cycle(start: usize, blockers: &[Option<usize>]) -> usize {
+    let mut seen = HashSet::new();
+    let mut idx = start;
+    while seen.insert(idx) {
+        match blockers[idx] {
+            Some(blocker) => idx = blocker,
+            None => break,
+        }
+    }
+
+    idx
+}
+
+// Copies and links leave the original files in place.
+// Nothing needs to move out of the way, so the steps follow the input file.
+pub fn plan_in_order(plan: &RenamePlan) -> Vec<Step> {
+    plan.entries()
+        .iter()
+        .filter(|e| plan::get_key(&e.origin) != plan::get_key(&e.destination))
+        .map(|e| Step {
+            origin: e.origin.to_path_buf(),
+            destination: e.destination.to_path_buf(),
+            temporary: false,
+        })
+        .collect()
+}
+
+// Temporary names are hidden files in the same directory,
+// so the renaming never crosses a file system.
+fn create_temp_names(fpath: &Path, names: &HashSet<PathBuf>) -> PathBuf {
+    let parent = fpath.parent().unwrap_or_else(|| Path::new(""));
+    let fname = fpath.file_name().map(OsString::from).unwrap_or_default();
+
+    let mut count = 0;
+    loop {
+        let mut temp = OsString::from(format!(".renamer-tmp{}_", count));
+        temp.push(&fname);
+        let temp = parent.join(temp);
+        if !temp.exists() && !names.contains(&temp) {
+            return temp;
+        }
+        count += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn to_plan(pairs: &[(&str, &str)]) -> RenamePlan {
+        let mut plan = RenamePlan::new();
+        pairs.iter()
+            .enumerate()
+            .for_each(|(i, (old, new))| {
+                plan.push(i + 2, PathBuf::from(old), PathBuf::from(new)).unwrap()
+            });
+        plan
+    }
+
+    // Runs the steps over a list of names instead of the file system.
+    fn simulate(steps: &[Step], files: &mut [PathBuf]) {
+        for step in steps {
+            assert!(!files.contains(&step.destination));
+            let idx = files.iter().position(|f| *f == step.origin).unwrap();
+            files[idx] = step.destination.to_path_buf();
+        }
+    }
+
+    #[test]
+    fn plan_chain_test() {
+        let plan = to_plan(&[("data/a.fq", "data/b.fq"), ("data/b.fq", "data/c.fq")]);
+        let steps = plan_renaming(&plan);
+
+        assert_eq!(2, steps.len());
+        assert_eq!(PathBuf::from("data/b.fq"), steps[0].origin);
+        assert_eq!(PathBuf::from("data/a.fq"), steps[1].origin);
+        assert!(steps.iter().all(|s| !s.temporary));
+    }
+
+    #[test]
+    fn plan_in_order_test() {
+        let plan = to_plan(&[
+            ("data/a.fq", "data/b.fq"),
+            ("data/b.fq", "data/a.fq"),
+            ("data/c.fq", "data/c.fq"),
+        ]);
+        let steps = plan_in_order(&plan);
+
+        assert_eq!(2, steps.len());
+        assert_eq!(PathBuf::from("data/a.fq"), steps[0].origin);
+        assert!(steps.iter().all(|s| !s.temporary));
+    }
+
+    #[test]
+    fn plan_swap_test() {
+        let plan = to_plan(&[("data/a.fq", "data/b.fq"), ("data/b.fq", "data/a.fq")]);
+        let steps = plan_renaming(&plan);
+        let mut files = vec![PathBuf::from("data/a.fq"), PathBuf::from("data/b.fq")];
+        simulate(&steps, &mut files);
+
+        assert_eq!(3, steps.len());
+        assert!(steps[0].temporary);
+        assert_eq!(vec![PathBuf::from("data/b.fq"), PathBuf::from("data/a.fq")], files);
+    }
+
+    #[test]
+    fn plan_rotation_test() {
+        let plan = to_plan(&[
+            ("data/a.fq", "data/b.fq"),
+            ("data/b.fq", "data/c.fq"),
+            ("data/c.fq", "data/a.fq"),
+            ("data/d.fq", "data/e.fq"),
+        ]);
+        let steps = plan_renaming(&plan);
+        let mut files: Vec<PathBuf> = ["a", "b", "c", "d"].iter()
+            .map(|f| PathBuf::from(format!("data/{}.fq", f)))
+            .collect();
+        simulate(&steps, &mut files);
+
+        let res: Vec<PathBuf> = ["b", "c", "a", "e"].iter()
+            .map(|f| PathBuf::from(format!("data/{}.fq", f)))
+            .collect();
+        assert_eq!(res, files);
+        assert_eq!(1, steps.iter().filter(|s| s.temporary).count());
+    }
+
+    #[test]
+    fn plan_long_chain_test() {
+        let names: Vec<String> = (0..=1000).map(|i| format!("data/{}.fq", i)).collect();
+        let pairs: Vec<(&str, &str)> = names.windows(2)
+            .map(|w| (w[0].as_str(), w[1].as_str()))
+            .collect();
+        let steps = plan_renaming(&to_plan(&pairs));
+        let mut files: Vec<PathBuf> = names[..1000].iter().map(PathBuf::from).collect();
+        simulate(&steps, &mut files);
+
+        assert_eq!(1000, steps.len());
+        assert!(steps.iter().all(|s| !s.temporary));
+        assert_eq!(PathBuf::from("data/1000.fq"), files[999]);
+    }
+
+    #[test]
+    fn plan_long_cycle_test() {
+        let names: Vec<String> = (0..1000).map(|i| format!("data/{}.fq", i)).collect();
+        let pairs: Vec<(&str, &str)> = names.iter()
+            .zip(names.iter().cycle().skip(1))
+            .map(|(old, new)| (old.as_str(), new.as_str()))
+            .collect();
+        let steps = plan_renaming(&to_plan(&pairs));
+        let mut files: Vec<PathBuf> = names.iter().map(PathBuf::from).collect();
+        simulate(&steps, &mut files);
+
+        assert_eq!(1001, steps.len());
+        assert_eq!(1, steps.iter().filter(|s| s.temporary).count());
+        assert_eq!(PathBuf::from("data/0.fq"), files[999]);
+    }
+
+    // Absolute and relative paths to the same file still form a chain.
+    #[test]
+    fn plan_mixed_paths_test() {
+        let absolute = std::env::current_dir().unwrap().join("data/a.fq");
+        let plan = to_plan(&[
+            (&absolute.to_string_lossy(), "data/b.fq"),
+            ("./data/b.fq", "data/c.fq"),
+        ]);
+        let steps = plan_renaming(&plan);
+
+        assert_eq!(2, steps.len());
+        assert_eq!(PathBuf::from("data/b.fq"), steps[0].origin);
+        assert_eq!(absolute, steps[1].origin);
+        assert!(steps.iter().all(|s| !s.temporary));
+    }
+
+    #[test]
+    fn plan_same_names_test() {
+        let plan = to_plan(&[("data/a.fq", "data/a.fq")]);
+        assert!(plan_renaming(&plan).is_empty());
+    }
+}
@@ -0,0 +1,104 @@
+use std::io::{self, BufRead, ErrorKind, IsTerminal};
+use std::path::{Path, PathBuf};
+
+use crate::checksum::Algorithm;
+use crate::conflict::OnConflict;
+use crate::error::Error;
+use crate::transfer::Mode;
+
+// What to do when a file can't be renamed.
+// Prompt asks the user and only applies when stdin is a terminal.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OnError {
+    #[default]
+    Prompt,
+    Skip,
+    Retry(u32),
+    AbortRollback,
+}
+
+impl OnError {
+    // Accepts skip, retry-N, or abort-rollback.
+    pub fn parse(policy: &str) -> Result<Self, String> {
+        match policy {
+            "skip" => Ok(OnError::Skip),
+            "abort-rollback" => Ok(OnError::AbortRollback),
+            p => match p.strip_prefix("retry-").map(|n| n.parse::<u32>()) {
+                Some(Ok(n)) if n > 0 => Ok(OnError::Retry(n)),
+                _ => Err(format!(
+                    "Invalid error policy: {}. Use skip, retry-N, or abort-rollback.", p
+                )),
+            },
+        }
+    }
+}
+
+// Options for running the renaming.
+// Yes continues despite errors in the input check without asking.
+// Portable also checks the new names against macOS and Windows rules.
+// Report is the json or csv file for the report of the check or the run.
+// Checksum verifies the renamed files and writes a manifest of them.
+// Companions adds the files that follow the renamed files, such as indexes.
+#[derive(Debug, Default)]
+pub struct RunOpts {
+    pub dryrun: bool,
+    pub yes: bool,
+    pub portable: bool,
+    pub companions: bool,
+    pub report: Option<PathBuf>,
+    pub checksum: Option<Algorithm>,
+    pub on_error: OnError,
+    pub on_conflict: OnConflict,
+    pub mode: Mode,
+}
+
+// We never prompt in pipes or cluster jobs.
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal()
+}
+
+// Reads a single letter answer from stdin, a line at a time.
+// Blank lines are ignored, so extra newlines are not counted as wrong answers.
+pub fn ask(question: &str, choices: &[u8]) -> Result<u8, Error> {
+    warn!("{}", question);
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let bytes = stdin.lock()
+            .read_line(&mut line)
+            .map_err(Error::io(Path::new("stdin")))?;
+
+        if bytes == 0 {
+            return Err(no_user_input());
+        }
+
+        match line.trim().as_bytes() {
+            [] => continue,
+            [c] if choices.contains(&c.to_ascii_lowercase()) => return Ok(c.to_ascii_lowercase()),
+            _ => warn!("Incorrect input! Please, try again..."),
+        }
+    }
+}
+
+pub fn no_user_input() -> Error {
+    Error::Io {
+        path: PathBuf::from("stdin"),
+        source: io::Error::new(ErrorKind::UnexpectedEof, "NO USER INPUT"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_on_error_test() {
+        assert_eq!(Ok(OnError::Skip), OnError::parse("skip"));
+        assert_eq!(Ok(OnError::Retry(3)), OnError::parse("retry-3"));
+        assert_eq!(Ok(OnError::AbortRollback), OnError::parse("abort-rollback"));
+        assert!(OnError::parse("retry-0").is_err());
+        assert!(OnError::parse("retry").is_err());
+        assert!(OnError::parse("abort").is_err());
+    }
+}
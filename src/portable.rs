@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::plan::{self, RenamePlan};
+use crate::transfer::Mode;
+
+// Names Windows keeps for devices, with or without an extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+const ILLEGAL_CHARS: &[char] = &['<', '>', ':', '"', '\\', '|', '?', '*'];
+
+// Names folded to lower case, built once for the whole batch.
+// Batch maps the plan keys of the new names to their rows, in input order.
+// Dirs maps each destination directory to the files already in it.
+pub struct FoldedNames {
+    batch: HashMap<String, Vec<usize>>,
+    dirs: HashMap<PathBuf, HashMap<String, Vec<PathBuf>>>,
+}
+
+impl FoldedNames {
+    // Each destination directory is read once.
+    pub fn new(plan: &RenamePlan) -> Self {
+        let mut batch: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut dirs = HashMap::new();
+        for (idx, e) in plan.entries().iter().enumerate() {
+            batch.entry(fold_case(&plan::get_key(&e.destination))).or_default().push(idx);
+            let dir = e.destination.parent().unwrap_or_else(|| Path::new(""));
+            if !dirs.contains_key(dir) {
+                dirs.insert(dir.to_path_buf(), read_folded_dir(dir));
+            }
+        }
+
+        Self { batch, dirs }
+    }
+}
+
+fn read_folded_dir(dir: &Path) -> HashMap<String, Vec<PathBuf>> {
+    let read_dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+    let mut existing: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    if let Ok(entries) = fs::read_dir(read_dir) {
+        entries.filter_map(|e| e.ok())
+            .map(|e| dir.join(e.file_name()))
+            .for_each(|path| existing.entry(fold_case(&path)).or_default().push(path));
+    }
+
+    existing
+}
+
+// Checks that the new name survives copying to macOS and Windows,
+// whose file systems ignore case by default.
+// Returns why the name is not portable.
+// Names differing only in case collide with other new names in the batch
+// and with existing files, unless the existing file is the one renamed,
+// or is renamed away in the batch.
+pub fn check_portable(
+    old: &Path,
+    new: &Path,
+    plan: &RenamePlan,
+    names: &FoldedNames,
+    mode: Mode
+) -> Option<String> {
+    let fname = new.file_name().unwrap_or_default().to_string_lossy();
+    if let Some(problem) = check_file_name(&fname) {
+        return Some(problem);
+    }
+
+    let key = plan::get_key(new);
+    let in_batch = names.batch.get(&fold_case(&key))
+        .into_iter()
+        .flatten()
+        .map(|&idx| &plan.entries()[idx])
+        .find(|e| plan::get_key(&e.destination) != key);
+    if let Some(e) = in_batch {
+        return Some(format!("SAME NAME IGNORING CASE AS {:?} IN LINE {}", e.destination, e.line));
+    }
+
+    let dir = new.parent().unwrap_or_else(|| Path::new(""));
+    let old_key = plan::get_key(old);
+    names.dirs.get(dir)
+        .and_then(|existing| existing.get(&fold_case(new)))
+        .into_iter()
+        .flatten()
+        .find(|existing| {
+            let existing_key = plan::get_key(existing);
+            existing_key != key
+                && existing_key != old_key
+                && !(mode == Mode::Rename && plan.contains_origin(existing))
+        })
+        .map(|existing| format!("SAME NAME IGNORING CASE AS EXISTING {:?}", existing))
+}
+
+fn check_file_name(fname: &str) -> Option<String> {
+    if let Some(c) = fname.chars().find(|c| ILLEGAL_CHARS.contains(c) || c.is_control()) {
+        return Some(format!("ILLEGAL CHARACTER {:?} ON WINDOWS", c));
+    }
+
+    if fname.ends_with('.') || fname.ends_with(' ') {
+        return Some(String::from("TRAILING DOT OR SPACE ON WINDOWS"));
+    }
+
+    let base = fname.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(base)) {
+        return Some(format!("RESERVED NAME {} ON WINDOWS", base.to_uppercase()));
+    }
+
+    None
+}
+
+fn fold_case(path: &Path) -> String {
+    path.to_string_lossy().to_lowercase()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+
+    #[test]
+    fn check_file_name_test() {
+        assert!(check_file_name("sample_1.fq.gz").is_none());
+        assert!(check_file_name("con.fq.gz").is_some());
+        assert!(check_file_name("LPT1").is_some());
+        assert!(check_file_name("console.fq").is_none());
+        assert!(check_file_name("sample.").is_some());
+        assert!(check_file_name("sample ").is_some());
+        assert!(check_file_name("a:b.fq").is_some());
+        assert!(check_file_name("a\tb.fq").is_some());
+    }
+
+    #[test]
+    fn check_portable_batch_test() {
+        let mut plan = RenamePlan::new();
+        plan.push(2, PathBuf::from("data/a.fq"), PathBuf::from("data/Sample1.fq")).unwrap();
+        plan.push(3, PathBuf::from("data/b.fq"), PathBuf::from("data/sample1.fq")).unwrap();
+        plan.push(4, PathBuf::from("data/c.fq"), PathBuf::from("data/sample2.fq")).unwrap();
+
+        let names = FoldedNames::new(&plan);
+
+        let res = check_portable(Path::new("data/a.fq"), Path::new("data/Sample1.fq"), &plan, &names, Mode::Rename);
+        assert!(res.unwrap().ends_with("IN LINE 3"));
+        assert!(check_portable(Path::new("data/c.fq"), Path::new("data/sample2.fq"), &plan, &names, Mode::Rename).is_none());
+    }
+
+    #[test]
+    fn check_portable_existing_test() {
+        let dir = env::temp_dir().join("renamer_portable_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        ["Sample1.fq", "b.fq", "Sample2.fq"].iter()
+            .for_each(|f| { fs::File::create(dir.join(f)).unwrap(); });
+
+        let mut plan = RenamePlan::new();
+        plan.push(2, dir.join("b.fq"), dir.join("sample1.fq")).unwrap();
+        plan.push(3, dir.join("Sample2.fq"), dir.join("sample2.fq")).unwrap();
+
+        let names = FoldedNames::new(&plan);
+
+        assert!(check_portable(&dir.join("b.fq"), &dir.join("sample1.fq"), &plan, &names, Mode::Rename).is_some());
+        assert!(check_portable(&dir.join("Sample2.fq"), &dir.join("sample2.fq"), &plan, &names, Mode::Rename).is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
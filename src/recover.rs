@@ -0,0 +1,320 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::checksum;
+use crate::conflict;
+use crate::error::Error;
+use crate::journal::{self, Journal, Record, Status};
+use crate::output;
+use crate::planner::Step;
+use crate::policy::{OnError, RunOpts};
+use crate::transfer::{self, Mode};
+use crate::renamer::{self, Summary};
+
+// Files renamed so far, in order.
+type Done = Vec<Record>;
+
+// Finishes or rolls back an interrupted run.
+// The recovery is appended to the same journal,
+// so the run can still be undone in one go.
+// A resumed run keeps the settings of the original run.
+// With a checksum, only the files renamed after resuming are verified.
+pub fn recover_files(path: &str, rollback: bool, on_error: OnError) -> Result<(), Error> {
+    let log = journal::read_journal(path)?;
+    if log.finished {
+        info!("The journal {} is complete. Nothing to recover.", path);
+        return Ok(());
+    }
+
+    let mut journal = Journal::open(path)?;
+    info!("Checking interrupted renaming...");
+    let (mut done, remaining) = resolve_steps(&log.records, log.mode, &mut journal)?;
+    info!("Files renamed before interruption: {}", done.iter().filter(|d| !d.status.is_aside()).count());
+    info!("Remaining files: {}", remaining.len());
+
+    if rollback {
+        let restored = renamer::roll_back_renaming(&done, &mut journal)?;
+        journal.finish()?;
+        info!("\nTotal files rolled back: {}", restored.len());
+    } else {
+        info!("Resuming renaming...");
+        remove_set_asides(&mut done, &mut journal)?;
+        let run = RunOpts {
+            on_error,
+            on_conflict: log.on_conflict,
+            checksum: log.checksum,
+            mode: log.mode,
+            ..RunOpts::default()
+        };
+        let before = match run.checksum {
+            Some(algo) => checksum::compute_step_checksums(&remaining, algo)?,
+            None => Vec::new(),
+        };
+        let mut summary = Summary::default();
+        renamer::run_steps(&remaining, &mut journal, &mut done, &run, &mut summary)?;
+        journal.finish()?;
+        renamer::display_summary(&summary, log.mode);
+        journal::display_journal(&journal);
+        if let Some(algo) = run.checksum {
+            checksum::check_renamed_files(&before, &summary.outcomes, &journal, algo)?;
+        }
+        return renamer::check_renaming(summary.renamed, summary.failed);
+    }
+
+    journal::display_journal(&journal);
+
+    Ok(())
+}
+
+// Sorts the planned steps into done and remaining.
+// A pending step was interrupted between its intent and outcome.
+// We look at the files to tell whether the renaming happened.
+// Copies and links only appear under the new name when complete.
+// Partial copies left next to the new name are removed.
+fn resolve_steps(
+    records: &[Record],
+    mode: Mode,
+    journal: &mut Journal
+) -> Result<(Done, Vec<Step>), Error> {
+    let mut planned = Vec::new();
+    let mut done = Vec::new();
+    let mut states: HashMap<PathBuf, (Status, PathBuf)> = HashMap::new();
+    let mut asides: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+    for rec in records {
+        match rec.status {
+            Status::Planned | Status::PlannedTemp => planned.push(Step {
+                origin: rec.origin.to_path_buf(),
+                destination: rec.destination.to_path_buf(),
+                temporary: rec.status == Status::PlannedTemp,
+            }),
+            status if status.is_done() => done.push(Record {
+                status,
+                origin: rec.origin.to_path_buf(),
+                destination: rec.destination.to_path_buf(),
+            }),
+            Status::RolledBack => {
+                done.retain(|d| d.destination != rec.destination || d.origin != rec.origin)
+            }
+            Status::Overwritten => {
+                done.retain(|d| d.status != Status::SetAside || d.origin != rec.origin)
+            }
+            Status::PendingAside => asides.push((rec.origin.to_path_buf(), rec.destination.to_path_buf())),
+            _ => (),
+        }
+
+        match rec.status {
+            Status::Planned | Status::PlannedTemp | Status::Overwritten => (),
+            status if status.is_aside() || status == Status::RolledBack => {
+                asides.retain(|(taken, aside)| *taken != rec.origin || *aside != rec.destination)
+            }
+            Status::PendingAside => (),
+            status => {
+                states.insert(rec.origin.to_path_buf(), (status, rec.destination.to_path_buf()));
+            }
+        }
+    }
+
+    let mut remaining = Vec::new();
+    for step in planned {
+        match states.get(&step.origin) {
+            None => remaining.push(step),
+            Some((Status::Pending, new_names)) => {
+                if let Some(temp) = transfer::remove_temp_copy(new_names).map_err(Error::io(new_names))? {
+                    info!("Removed the partial copy {:?}", temp);
+                }
+                let copied = mode != Mode::Rename && fs::symlink_metadata(new_names).is_ok();
+                if copied || (!step.origin.exists() && new_names.is_file()) {
+                    let status = renamer::done_status(mode, &None);
+                    journal.record(status, &step.origin, new_names)?;
+                    done.push(Record {
+                        status,
+                        origin: step.origin.to_path_buf(),
+                        destination: new_names.to_path_buf(),
+                    });
+                } else if step.origin.is_file() && !new_names.exists() {
+                    remaining.push(step);
+                } else {
+                    warn!("{}", output::yellow(format!("CAN'T TELL IF {:?} WAS RENAMED. SKIPPING IT...",
+                        step.origin)));
+                    journal.record(Status::Skipped, &step.origin, new_names)?;
+                }
+            }
+            Some(_) => (),
+        }
+    }
+
+    restore_asides(&mut done, &asides, journal)?;
+
+    Ok((done, remaining))
+}
+
+// Files moved out of the way of a new name are put back
+// unless the file taking the name is done.
+// A move out of the way cut short is told apart by the files.
+fn restore_asides(done: &mut Done, pending: &[(PathBuf, PathBuf)], journal: &mut Journal) -> Result<(), Error> {
+    let mut asides: Vec<(PathBuf, PathBuf)> = pending.iter()
+        .filter(|(taken, aside)| !conflict::is_taken(taken) && conflict::is_taken(aside))
+        .cloned()
+        .collect();
+    let renamed: HashSet<PathBuf> = done.iter()
+        .filter(|d| !d.status.is_aside())
+        .map(|d| d.destination.to_path_buf())
+        .collect();
+    done.retain(|d| {
+        let left = d.status.is_aside() && !renamed.contains(&d.origin);
+        if left {
+            asides.push((d.origin.to_path_buf(), d.destination.to_path_buf()));
+        }
+        !left
+    });
+
+    for (taken, aside) in asides {
+        if conflict::is_taken(&taken) {
+            warn!("{:?} {} Keeping {:?}...", taken, output::red("ALREADY EXISTS!"), aside);
+            continue;
+        }
+        fs::rename(&aside, &taken).map_err(Error::io(&aside))?;
+        journal.record(Status::RolledBack, &taken, &aside)?;
+        info!("Restored {:?}", taken);
+    }
+
+    Ok(())
+}
+
+// Files set aside to be overwritten are deleted
+// before the run goes on, as the renaming would have done.
+fn remove_set_asides(done: &mut Done, journal: &mut Journal) -> Result<(), Error> {
+    let origins: HashMap<PathBuf, PathBuf> = done.iter()
+        .filter(|d| !d.status.is_aside())
+        .map(|d| (d.destination.to_path_buf(), d.origin.to_path_buf()))
+        .collect();
+    let mut removed = Vec::new();
+    for rec in done.iter().filter(|d| d.status == Status::SetAside) {
+        if let Err(e) = fs::remove_file(&rec.destination) {
+            warn!("{} {}", output::red(format!("CAN'T REMOVE {:?}.", rec.destination)), e);
+            continue;
+        }
+        if let Some(origin) = origins.get(&rec.origin) {
+            journal.record(Status::Overwritten, &rec.origin, origin)?;
+        }
+        removed.push(rec.destination.to_path_buf());
+    }
+    done.retain(|d| d.status != Status::SetAside || !removed.contains(&d.destination));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::path::Path;
+    use crate::checksum::Algorithm;
+    use crate::conflict::OnConflict;
+
+    // Simulates a run killed while renaming the second file.
+    fn interrupted_run(dir: &Path, run: &RunOpts) -> (String, Vec<PathBuf>) {
+        fs::remove_dir_all(dir).ok();
+        fs::create_dir_all(dir).unwrap();
+        let names: Vec<PathBuf> = ["a", "b", "c", "x", "y", "z"].iter()
+            .map(|f| dir.join(format!("{}.fq.gz", f)))
+            .collect();
+        names[..3].iter().for_each(|f| { fs::File::create(f).unwrap(); });
+
+        let steps: Vec<Step> = (0..3)
+            .map(|i| Step {
+                origin: names[i].to_path_buf(),
+                destination: names[i + 3].to_path_buf(),
+                temporary: false,
+            })
+            .collect();
+
+        let input = dir.join("input.csv");
+        let mut journal = Journal::create(&input.to_string_lossy(), run).unwrap();
+        journal.record_plan(&steps).unwrap();
+        journal.record_intent(&names[0], &names[3]).unwrap();
+        fs::rename(&names[0], &names[3]).unwrap();
+        journal.record(Status::Renamed, &names[0], &names[3]).unwrap();
+        journal.record_intent(&names[1], &names[4]).unwrap();
+        fs::rename(&names[1], &names[4]).unwrap();
+
+        (journal.path().to_string_lossy().into_owned(), names)
+    }
+
+    #[test]
+    fn recover_resume_test() {
+        let dir = env::temp_dir().join("renamer_recover_resume_test");
+        let (path, names) = interrupted_run(&dir, &RunOpts::default());
+        let partial = dir.join(".renamer-copy_y.fq.gz");
+        fs::File::create(&partial).unwrap();
+        recover_files(&path, false, OnError::Skip).unwrap();
+
+        assert!(!partial.exists());
+        assert!(names[..3].iter().all(|f| !f.exists()));
+        assert!(names[3..].iter().all(|f| f.is_file()));
+        assert!(journal::read_journal(&path).unwrap().finished);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // The resumed run backs up the taken name and verifies the checksums,
+    // as the original run was set to.
+    #[test]
+    fn recover_run_settings_test() {
+        let dir = env::temp_dir().join("renamer_recover_run_settings_test");
+        let run = RunOpts {
+            on_conflict: OnConflict::Backup,
+            checksum: Some(Algorithm::Sha256),
+            ..RunOpts::default()
+        };
+        let (path, names) = interrupted_run(&dir, &run);
+        fs::write(&names[5], "taken").unwrap();
+        recover_files(&path, false, OnError::Skip).unwrap();
+
+        assert_eq!("taken", fs::read_to_string(dir.join("z.fq.gz.bak")).unwrap());
+        assert!(names[5].is_file());
+        let manifest = fs::read_dir(&dir).unwrap()
+            .filter_map(|e| e.ok())
+            .find(|e| checksum::is_manifest(&e.path()));
+        assert!(manifest.is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Killed after setting aside the file holding the new name.
+    // Rolling back puts it back in place.
+    #[test]
+    fn recover_set_aside_test() {
+        let dir = env::temp_dir().join("renamer_recover_set_aside_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let (old, new, aside) = (dir.join("a.fq"), dir.join("x.fq"), dir.join(".renamer-old1_x.fq"));
+        fs::write(&old, "a").unwrap();
+        fs::write(&new, "taken").unwrap();
+        let step = Step { origin: old.to_path_buf(), destination: new.to_path_buf(), temporary: false };
+
+        let input = dir.join("input.csv");
+        let mut journal = Journal::create(&input.to_string_lossy(), &RunOpts::default()).unwrap();
+        journal.record_plan(&[step]).unwrap();
+        journal.record_aside_intent(&new, &aside).unwrap();
+        fs::rename(&new, &aside).unwrap();
+        let path = journal.path().to_string_lossy().into_owned();
+        recover_files(&path, true, OnError::Skip).unwrap();
+
+        assert_eq!("a", fs::read_to_string(&old).unwrap());
+        assert_eq!("taken", fs::read_to_string(&new).unwrap());
+        assert!(!aside.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recover_rollback_test() {
+        let dir = env::temp_dir().join("renamer_recover_rollback_test");
+        let (path, names) = interrupted_run(&dir, &RunOpts::default());
+        recover_files(&path, true, OnError::Skip).unwrap();
+
+        assert!(names[..3].iter().all(|f| f.is_file()));
+        assert!(names[3..].iter().all(|f| !f.exists()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
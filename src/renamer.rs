@@ -1,158 +1,628 @@
-use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fs;
-use std::io::{self, Read, Error, ErrorKind};
-use std::io::prelude::*;
-use std::path::PathBuf;
-use std::process;
-
-use crate::parser;
-
-pub fn rename_files(path: &str) -> Result<(), Error> {
-    let dryrun = false;
-    let filenames = parser::parse_csv(path, dryrun);
-
-    // Keep track file renaming.
-    // Opposite insertion. The new_names is the key.
-    let mut temp: HashMap<PathBuf, PathBuf> = HashMap::new();
-    let mut rename_count = 0;
-
-    println!("Renaming files...");
-    for (origin, destination) in filenames.iter() {
-        let new_names = check_new_names(&destination);
-        
-        match fs::rename(origin, &new_names) {
-            Ok(()) => {
-                temp.insert(new_names.to_path_buf(), origin.to_path_buf());
-                display_result(origin, &new_names);
-                rename_count += 1;
-            },
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::checker::Checked;
+use crate::checksum;
+use crate::companion::{self, Rule};
+use crate::error::Error;
+use crate::conflict::{self, OnConflict, Resolution};
+use crate::journal::{self, Journal, Record, Status};
+use crate::parser::{self, InputOpts};
+use crate::plan::{self, RenamePlan};
+use crate::planner::{self, Step};
+use crate::output::{self, Progress};
+use crate::policy::{self, OnError, RunOpts};
+use crate::report;
+use crate::transfer::{self, Mode};
+
+// Gives other programs time to release the file before retrying.
+const RETRY_WAIT: Duration = Duration::from_secs(1);
+
+pub fn rename_files(path: &str, opts: &InputOpts, run: &RunOpts) -> Result<(), Error> {
+    let (plan, checks) = parser::parse_input(path, opts, run)?;
+    rename_plan(path, &plan, &checks, run)
+}
+
+// Renames a checked plan. The journal is saved next to the input,
+// or inside it when the input is a directory.
+// With a checksum, the files are hashed before and after renaming.
+pub fn rename_plan(path: &str, plan: &RenamePlan, checks: &[Checked], run: &RunOpts) -> Result<(), Error> {
+    let steps = match run.mode {
+        Mode::Rename => planner::plan_renaming(plan),
+        _ => planner::plan_in_order(plan),
+    };
+    if run.on_conflict == OnConflict::Error {
+        check_conflicts(&steps, run.mode)?;
+    }
+    let before = match run.checksum {
+        Some(algo) => checksum::compute_plan_checksums(plan, algo)?,
+        None => Vec::new(),
+    };
+    let mut journal = Journal::create(path, run)?;
+    journal.record_plan(&steps)?;
+
+    // Keep track file renaming in order.
+    let mut done: Vec<Record> = Vec::new();
+
+    info!("Renaming files...");
+    let mut summary = Summary::default();
+    match run_steps(&steps, &mut journal, &mut done, run, &mut summary) {
+        Err(Error::Aborted) => {
+            report::report_run(path, plan, checks, &summary.outcomes, run)?;
+            return Err(Error::Aborted);
+        }
+        res => res?,
+    }
+    journal.finish()?;
+
+    display_summary(&summary, run.mode);
+    journal::display_journal(&journal);
+    report::report_run(path, plan, checks, &summary.outcomes, run)?;
+    if let Some(algo) = run.checksum {
+        checksum::check_renamed_files(&before, &summary.outcomes, &journal, algo)?;
+    }
+
+    check_renaming(summary.renamed, summary.failed)
+}
+
+// Renaming that skipped some files is reported as an error,
+// so scripts can tell it apart from a complete run.
+pub fn check_renaming(done: u32, failed: u32) -> Result<(), Error> {
+    if failed > 0 {
+        return Err(Error::Partial { done, failed });
+    }
+
+    Ok(())
+}
+
+// Stops before renaming anything if a new name is taken
+// by a file that stays in place.
+fn check_conflicts(steps: &[Step], mode: Mode) -> Result<(), Error> {
+    let origins: HashSet<PathBuf> = steps.iter().map(|s| plan::get_key(&s.origin)).collect();
+    let taken: Vec<PathBuf> = steps.iter()
+        .filter(|s| !s.temporary && conflict::is_taken(&s.destination))
+        .filter(|s| mode != Mode::Rename || !origins.contains(&plan::get_key(&s.destination)))
+        .map(|s| s.destination.to_path_buf())
+        .collect();
+
+    if !taken.is_empty() {
+        return Err(Error::Conflicts(taken));
+    }
+
+    Ok(())
+}
+
+// The outcome of a run.
+// Conflicts lists every choice made for taken names.
+// Outcomes keep the result of each step for the report.
+// Diverted are the outcomes of files that didn't get their proposed name.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub renamed: u32,
+    pub failed: u32,
+    pub conflicts: Vec<String>,
+    pub outcomes: Vec<Outcome>,
+    diverted: Vec<usize>,
+}
+
+// Destination is the proposed name. New names is the name the file got.
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub destination: PathBuf,
+    pub new_names: PathBuf,
+    pub status: Status,
+    pub resolution: Option<String>,
+}
+
+impl Summary {
+    // Temporary names are not counted.
+    fn count(&mut self, step: &Step, new_names: &Path, status: Status, resolution: Option<String>) {
+        if step.temporary {
+            return;
+        }
+
+        if status.is_done() {
+            self.renamed += 1;
+        } else {
+            self.failed += 1;
+        }
+
+        if let Some(resolution) = &resolution {
+            self.conflicts.push(resolution.to_string());
+        }
+
+        if !status.is_done() || new_names != step.destination {
+            self.diverted.push(self.outcomes.len());
+        }
+
+        self.outcomes.push(Outcome {
+            destination: step.destination.to_path_buf(),
+            new_names: new_names.to_path_buf(),
+            status,
+            resolution,
+        });
+    }
+
+    // The outcome of the file the step is a companion of,
+    // when the file didn't get its proposed name.
+    fn find_diverted_file(&self, step: &Step) -> Option<(Outcome, Rule)> {
+        self.diverted.iter()
+            .map(|&i| &self.outcomes[i])
+            .find_map(|o| companion::find_rule(&o.destination, &step.destination).map(|r| (o.clone(), r)))
+    }
+}
+
+// Renames files step by step. Counts the files renamed
+// and the files not renamed, not counting the temporary names.
+// Done contains the renaming so far, including backups of taken names.
+// Aborting rolls all of it back. The summary is kept for the report.
+// Copies and links follow the same steps, leaving the originals in place.
+// Large batches show a progress bar in place of a line per file.
+// Errors are still shown for each file.
+// Companions follow the name their file got.
+// They are skipped when their file was not renamed.
+pub fn run_steps(
+    steps: &[Step],
+    journal: &mut Journal,
+    done: &mut Vec<Record>,
+    run: &RunOpts,
+    summary: &mut Summary
+) -> Result<(), Error> {
+    let total = steps.iter().filter(|s| !s.temporary).count();
+    let show = output::show_files(total);
+    let mut progress = Progress::new(total);
+    for step in steps.iter() {
+        if !step.temporary {
+            progress.tick();
+        }
 
-            Err(error) => match error.kind() {
-
-                ErrorKind::PermissionDenied => { 
-                    println!("Can't rename {:?}. It may be used by another program.", origin);
-                    
-                    let input =  get_user_input();
-                    match input {
-
-                        b'r' => { 
-                            match fs::rename(origin, &new_names) {
-                                Ok(()) => {
-                                    temp.insert(new_names.to_path_buf(), origin.to_path_buf());
-                                    display_result(origin, &new_names);
-                                    rename_count += 1;
-                                }
-                                Err(_) => {
-                                    println!("Still can't rename {:?}. Skipping it...", origin);
-                                    continue;
-                                }
-                            }
-                        }
-
-                        b'c' => {
-                            println!("Skipping {:?}", origin);
-                            continue;
-                        },
-
-                        b'a' => {
-                            roll_back_renaming(&temp);
-                            process::abort();
-                        }
-                        _ => panic!("UNKNOWN ERRORS COMING FROM USER INPUTS!")
-                    }   
+        let origin = &step.origin;
+        let diverted = match run.companions {
+            true => summary.find_diverted_file(step),
+            false => None,
+        };
+        let destination = match diverted {
+            None => step.destination.to_path_buf(),
+            Some((file, rule)) => match rule.apply(&file.new_names).filter(|_| file.status.is_done()) {
+                Some(destination) => destination,
+                None => {
+                    warn!("{:?} {} Skipping its companion {:?}...",
+                        file.destination, output::red("NOT RENAMED!"), origin);
+                    journal.record(Status::Skipped, origin, &step.destination)?;
+                    let resolution = format!("{:?} skipped with {:?}.", origin, file.destination);
+                    summary.count(step, &step.destination, Status::Skipped, Some(resolution));
+                    continue;
+                }
+            },
+        };
+        let resolution = match conflict::resolve_conflict(&destination, run.on_conflict) {
+            Ok(resolution) => resolution,
+            Err(source) => {
+                let error = Error::Rename {
+                    origin: origin.to_path_buf(),
+                    destination: destination.to_path_buf(),
+                    source,
+                };
+                journal.record(Status::Skipped, origin, &destination)?;
+                summary.count(step, &destination, Status::Skipped, None);
+                if run.on_error == OnError::AbortRollback {
+                    warn!("{} Aborting...", output::red(&error));
+                    progress.finish();
+                    return abort_renaming(done, journal, summary);
                 }
+                warn!("{} Skipping it...", output::red(&error));
+                continue;
+            }
+        };
+        let new_names = match &resolution {
+            Resolution::Rename(new_names) => new_names.to_path_buf(),
+            _ => destination,
+        };
+
+        if resolution == Resolution::Error || resolution == Resolution::Skip {
+            warn!("{:?} {} Skipping {:?}...", new_names, output::red("ALREADY EXISTS!"), origin);
+            journal.record(Status::Skipped, origin, &new_names)?;
+            let resolution = format!("{:?} skipped. {:?} already exists.", origin, new_names);
+            summary.count(step, &new_names, Status::Skipped, Some(resolution));
+            continue;
+        }
 
-                ErrorKind::NotFound => {
-                    println!("{:?} \x1b[0;41mNOT FOUND!\x1b[0m", origin);
+        let set_aside = match &resolution {
+            Resolution::Overwrite { set_aside } | Resolution::Backup(set_aside) => {
+                if fs::symlink_metadata(&new_names).is_ok_and(|m| m.is_dir()) {
+                    warn!("{:?} {} Skipping {:?}...", new_names, output::red("IS A DIRECTORY!"), origin);
+                    journal.record(Status::Skipped, origin, &new_names)?;
+                    let resolution = format!("{:?} skipped. {:?} is a directory.", origin, new_names);
+                    summary.count(step, &new_names, Status::Skipped, Some(resolution));
+                    continue;
+                }
+                journal.record_aside_intent(&new_names, set_aside)?;
+                if let Err(e) = fs::rename(&new_names, set_aside) {
+                    warn!("{} {} Skipping it...",
+                        output::red(format!("CAN'T MOVE {:?} OUT OF THE WAY.", new_names)), e);
+                    journal.record(Status::Skipped, origin, &new_names)?;
+                    summary.count(step, &new_names, Status::Skipped, None);
                     continue;
                 }
+                let status = match resolution {
+                    Resolution::Backup(_) => Status::BackedUp,
+                    _ => Status::SetAside,
+                };
+                journal.record(status, &new_names, set_aside)?;
+                done.push(Record {
+                    status,
+                    origin: new_names.to_path_buf(),
+                    destination: set_aside.to_path_buf(),
+                });
+                Some(set_aside)
+            }
+            _ => None,
+        };
+
+        journal.record_intent(origin, &new_names)?;
+        match try_rename(origin, &new_names, run) {
+            Ok((status, checksum)) if status.is_done() => {
+                done.push(Record {
+                    status,
+                    origin: origin.to_path_buf(),
+                    destination: new_names.to_path_buf(),
+                });
+                journal.record(status, origin, &new_names)?;
+                if let Some(checksum) = checksum {
+                    journal.record_checksum(&new_names, &checksum)?;
+                    if status == Status::Moved && show {
+                        info!("{}", output::yellow("Copied across filesystems. Checksum verified."));
+                    }
+                }
+                if show {
+                    display_result(origin, &new_names);
+                }
+                let choice = resolve_set_aside(&resolution, origin, &new_names, journal, done)?;
+                summary.count(step, &new_names, status, choice);
+            }
 
-                unknown_error => {
-                    panic!("CAN'T RENAME THE FILE {:?}", unknown_error);
+            Ok((status, _)) => {
+                journal.record(status, origin, &new_names)?;
+                if let Some(set_aside) = set_aside {
+                    restore_set_aside(&new_names, set_aside, journal, done)?;
                 }
+                summary.count(step, &new_names, status, None);
             }
+
+            Err(Error::Aborted) => {
+                progress.finish();
+                journal.record(Status::Skipped, origin, &new_names)?;
+                summary.count(step, &new_names, Status::Skipped, None);
+                if let Some(set_aside) = set_aside {
+                    restore_set_aside(&new_names, set_aside, journal, done)?;
+                }
+                return abort_renaming(done, journal, summary);
+            }
+
+            Err(e) => return Err(e),
         }
     }
-    println!("\nTotal files renamed: {}", rename_count);
 
+    progress.finish();
     Ok(())
 }
 
-fn get_user_input() -> u8 {
-    println!("What would you like to do: [r]etry/[c]ontinue/[a]bort? ");
+// Rolls back the files done so far and closes the journal.
+// Only the files put back are counted as rolled back.
+fn abort_renaming(done: &[Record], journal: &mut Journal, summary: &mut Summary) -> Result<(), Error> {
+    let restored: HashSet<PathBuf> = roll_back_renaming(done, journal)?.into_iter().collect();
+    summary.outcomes
+        .iter_mut()
+        .filter(|o| o.status.is_done() && restored.contains(&o.new_names))
+        .for_each(|o| o.status = Status::RolledBack);
+    journal.finish()?;
+    journal::display_journal(journal);
+    Err(Error::Aborted)
+}
+
+// Overwritten files are only deleted after the renaming succeeded.
+// A set-aside file that can't be deleted is kept, and undo restores it.
+// Returns the choice made for the taken name for the summary.
+fn resolve_set_aside(
+    resolution: &Resolution,
+    origin: &Path,
+    new_names: &Path,
+    journal: &mut Journal,
+    done: &mut Vec<Record>
+) -> Result<Option<String>, Error> {
+    let choice = match resolution {
+        Resolution::Rename(_) => format!(
+            "{:?} renamed to {:?}. The proposed name is taken.", origin, new_names
+        ),
+        Resolution::Backup(backup) => format!(
+            "{:?} backed up as {:?} before {:?} took its name.", new_names, backup, origin
+        ),
+        Resolution::Overwrite { set_aside } => match fs::remove_file(set_aside) {
+            Ok(_) => {
+                journal.record(Status::Overwritten, new_names, origin)?;
+                done.retain(|d| d.status != Status::SetAside || d.origin != new_names);
+                format!("{:?} overwritten by {:?}.", new_names, origin)
+            }
+            Err(e) => {
+                warn!("{} {}", output::red(format!("CAN'T REMOVE {:?}.", set_aside)), e);
+                format!("{:?} kept as {:?} after {:?} took its name.", new_names, set_aside, origin)
+            }
+        },
+        _ => return Ok(None),
+    };
+
+    Ok(Some(choice))
+}
+
+// Puts the file that held the new name back
+// when the renaming didn't happen.
+fn restore_set_aside(
+    new_names: &Path,
+    set_aside: &Path,
+    journal: &mut Journal,
+    done: &mut Vec<Record>
+) -> Result<(), Error> {
+    fs::rename(set_aside, new_names).map_err(Error::io(set_aside))?;
+    journal.record(Status::RolledBack, new_names, set_aside)?;
+    done.pop();
 
-    let mut input;
+    Ok(())
+}
+
+pub fn display_summary(summary: &Summary, mode: Mode) {
+    info!("\nTotal files {}: {}", mode.past_tense(), summary.renamed);
+    if !summary.conflicts.is_empty() {
+        info!("{}", output::yellow(format!("Taken names resolved: {}", summary.conflicts.len())));
+        summary.conflicts.iter().for_each(|c| info!("  {}", c));
+    }
+}
+
+// Renames a file following the error policy.
+// Returns the status to record in the journal,
+// with the checksum of files copied across filesystems.
+// Missing files are not retried.
+fn try_rename(
+    origin: &Path,
+    new_names: &Path,
+    run: &RunOpts
+) -> Result<(Status, Option<String>), Error> {
+    let on_error = run.on_error;
+    let mut attempts = 0;
     loop {
-        input = io::stdin()
-            .bytes()
-            .next()
-            .and_then(|ok| ok.ok())
-            .map(|key| key as u8)
-            .unwrap();
-        
-        match input {
-            b'c' | b'r' | b'a' => break,
-            _ => println!("Incorrect input! Please, try again...")
+        let moved = create_parent_dir(origin, new_names)
+            .and_then(|_| transfer::transfer_file(run.mode, origin, new_names));
+        let error = match moved {
+            Ok(checksum) => return Ok((done_status(run.mode, &checksum), checksum)),
+            Err(error) => error,
         };
-    }
 
-    input
+        let kind = error.kind();
+        if kind == ErrorKind::NotFound && on_error != OnError::AbortRollback {
+            warn!("{:?} {}", origin, output::red("NOT FOUND!"));
+            return Ok((Status::NotFound, None));
+        }
+
+        let error = Error::Rename {
+            origin: origin.to_path_buf(),
+            destination: new_names.to_path_buf(),
+            source: error,
+        };
+
+        match on_error {
+            OnError::Prompt if kind == ErrorKind::PermissionDenied => {
+                warn!("Can't rename {:?}. It may be used by another program.", origin);
+                match get_user_input()? {
+                    b'r' => continue,
+                    b'c' => {
+                        warn!("Skipping {:?}", origin);
+                        return Ok((Status::Skipped, None));
+                    }
+                    _ => return Err(Error::Aborted),
+                }
+            }
+
+            OnError::Retry(retries) if attempts < retries => {
+                attempts += 1;
+                warn!("{} Retrying {}/{}...", output::yellow(&error), attempts, retries);
+                thread::sleep(RETRY_WAIT);
+            }
+
+            OnError::AbortRollback => {
+                warn!("{} Aborting...", output::red(&error));
+                return Err(Error::Aborted);
+            }
+
+            _ => {
+                warn!("{} Skipping it...", output::red(&error));
+                return Ok((Status::Skipped, None));
+            }
+        }
+    }
 }
 
-fn check_new_names(destination: &PathBuf) -> PathBuf {
-    if destination.is_file() {
-        create_duplicate_names(&destination)
-    } else {
-        PathBuf::from(destination)
+pub fn done_status(mode: Mode, checksum: &Option<String>) -> Status {
+    match mode {
+        Mode::Rename if checksum.is_some() => Status::Moved,
+        Mode::Rename => Status::Renamed,
+        Mode::Copy => Status::Copied,
+        Mode::Hardlink => Status::Linked,
+        Mode::Symlink => Status::Symlinked,
     }
 }
 
-fn create_duplicate_names(fpath: &PathBuf) -> PathBuf {
-    let stem = fpath.file_stem().unwrap().to_string_lossy();
-    let ext = fpath.extension().unwrap().to_string_lossy();
-    let mut new_names = format!("{}_renamerdup.{}", &stem, &ext);
+// Reverses a past run using its journal.
+// Only files that were renamed and not rolled back are restored.
+// Copies and links are removed instead.
+// Latest renaming is undone first.
+pub fn undo_files(path: &str) -> Result<(), Error> {
+    let log = journal::read_journal(path)?;
+    if !log.finished {
+        return Err(Error::UnfinishedJournal(PathBuf::from(path)));
+    }
 
-    match &stem {
-        s if s.ends_with(".fastq") => {
-            let new_stem = stem.replace(".fastq", "_renamerdup.fastq");
-            new_names = format!("{}.{}", &new_stem, &ext);
+    let mut journal = Journal::create(path, &RunOpts::default())?;
+    let mut undo_count = 0;
+    let mut failed = 0;
+    let done = get_done_records(&log.records);
+    let total = done.len();
+    let show = output::show_files(total);
+    let mut progress = Progress::new(total);
+
+    info!("Undoing renaming...");
+    for rec in done.into_iter().rev() {
+        progress.tick();
+        if rec.status.keeps_origin() {
+            match remove_copy(&rec.destination, &rec.origin, &mut journal, show)? {
+                true => undo_count += 1,
+                false => failed += 1,
+            }
+            continue;
         }
 
-        s if s.ends_with(".fq") => {
-            let new_stem = stem.replace(".fq", "_renamerdup.fq");
-            new_names = format!("{}.{}", &new_stem, &ext);
+        if !conflict::is_taken(&rec.destination) {
+            warn!("{:?} {}", rec.destination, output::red("NOT FOUND!"));
+            journal.record(Status::NotFound, &rec.destination, &rec.origin)?;
+            failed += 1;
+            continue;
         }
 
-        s if s.ends_with(".fasta") => {
-            let new_stem = stem.replace(".fasta", "_renamerdup.fasta");
-            new_names = format!("{}.{}", &new_stem, &ext);
+        if rec.origin.exists() {
+            warn!("{:?} {} Skipping it...", rec.origin, output::red("ALREADY EXISTS!"));
+            journal.record(Status::Skipped, &rec.destination, &rec.origin)?;
+            failed += 1;
+            continue;
         }
 
-        _ => (),
+        journal.record_intent(&rec.destination, &rec.origin)?;
+        let checksum = transfer::move_file(&rec.destination, &rec.origin)
+            .map_err(|source| Error::Rename {
+                origin: rec.destination.to_path_buf(),
+                destination: rec.origin.to_path_buf(),
+                source,
+            })?;
+        match checksum {
+            Some(checksum) => {
+                journal.record(Status::Moved, &rec.destination, &rec.origin)?;
+                journal.record_checksum(&rec.origin, &checksum)?;
+            }
+            None => journal.record(Status::Renamed, &rec.destination, &rec.origin)?,
+        }
+        if show {
+            display_result(&rec.destination, &rec.origin);
+        }
+        undo_count += 1;
     }
 
-    let fnames = PathBuf::from(&new_names);
-    let path = fpath.parent().unwrap();
+    progress.finish();
+    journal.finish()?;
 
-    path.join(&fnames)
+    info!("\nTotal files restored: {}", undo_count);
+    journal::display_journal(&journal);
+
+    check_renaming(undo_count, failed)
 }
 
-fn roll_back_renaming(filenames: &HashMap<PathBuf, PathBuf>) {
-    println!("Rolling back!");
-    filenames.iter()
-        .for_each(|(new, old)| {
-            fs::rename(new, old).unwrap();
-            display_result(new, old);
-        });
+// A copy or link is only removed while the original is still there,
+// so undo never deletes the last copy of a file.
+fn remove_copy(copy: &Path, origin: &Path, journal: &mut Journal, show: bool) -> Result<bool, Error> {
+    if fs::symlink_metadata(copy).is_err() {
+        warn!("{:?} {}", copy, output::red("NOT FOUND!"));
+        journal.record(Status::NotFound, copy, origin)?;
+        return Ok(false);
+    }
+
+    if !origin.is_file() {
+        warn!("{:?} {} Keeping {:?}...", origin, output::red("ORIGINAL NOT FOUND!"), copy);
+        journal.record(Status::Skipped, copy, origin)?;
+        return Ok(false);
+    }
+
+    journal.record_intent(copy, origin)?;
+    fs::remove_file(copy).map_err(Error::io(copy))?;
+    journal.record(Status::Removed, copy, origin)?;
+    if show {
+        info!("{:?} {}", copy, output::cyan(" removed"));
+    }
+
+    Ok(true)
+}
+
+// Move mode may send files to directories that don't exist yet.
+// Missing files leave no empty directories behind.
+fn create_parent_dir(origin: &Path, new_names: &Path) -> io::Result<()> {
+    match new_names.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() && !dir.exists() && origin.exists() => {
+            fs::create_dir_all(dir)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn get_user_input() -> Result<u8, Error> {
+    policy::ask("What would you like to do: [r]etry/[c]ontinue/[a]bort? ", b"rca")
+}
+
+// Latest renaming is rolled back first,
+// so temporary names from cycles are restored in the right order.
+// Copies and links are removed, leaving the originals as they are.
+// Files that can't be restored are reported and left in the journal as renamed.
+// A file is never moved back over a name taken since.
+pub fn roll_back_renaming(done: &[Record], journal: &mut Journal) -> Result<Vec<PathBuf>, Error> {
+    warn!("Rolling back!");
+    let mut rolled_back = Vec::new();
+    for rec in done.iter().rev() {
+        let (new, old) = (&rec.destination, &rec.origin);
+        if !rec.status.keeps_origin() && conflict::is_taken(old) {
+            warn!("{} {:?} {}", output::red(format!("CAN'T ROLL BACK {:?}.", new)), old, output::red("ALREADY EXISTS!"));
+            continue;
+        }
+        let restored = match rec.status.keeps_origin() {
+            false => transfer::move_file(new, old).map(|_| ()),
+            true => fs::remove_file(new),
+        };
+
+        match restored {
+            Ok(_) => {
+                journal.record(Status::RolledBack, old, new)?;
+                display_result(new, old);
+                rolled_back.push(new.to_path_buf());
+            }
+            Err(e) => warn!("{} {}", output::red(format!("CAN'T ROLL BACK {:?}.", new)), e),
+        }
+    }
+
+    Ok(rolled_back)
 }
 
 // I call it current and new for the function arguments
 // Because this function is used for rolling back as well.
-fn display_result(current: &PathBuf, new: &PathBuf) {
-    let stdout = io::stdout();
-    let mut buff = io::BufWriter::new(stdout);
+fn display_result(current: &Path, new: &Path) {
+    info!("{:?} {} {:?}", current, output::cyan(" => "), new);
+}
+
+// Files renamed, copied, or set aside, in the order they were done.
+// Steps rolled back after an error are dropped,
+// and so are files set aside and then overwritten.
+fn get_done_records(records: &[Record]) -> Vec<&Record> {
+    let mut done: Vec<&Record> = Vec::new();
+    for rec in records {
+        match rec.status {
+            Status::RolledBack => {
+                done.retain(|d| d.destination != rec.destination || d.origin != rec.origin)
+            }
+            Status::Overwritten => {
+                done.retain(|d| d.status != Status::SetAside || d.origin != rec.origin)
+            }
+            status if status.is_done() => done.push(rec),
+            _ => (),
+        }
+    }
 
-    writeln!(buff, "{:?} \x1b[0;36m => \x1b[0m {:?}", current, new).unwrap();
+    done
 }
 
 #[cfg(test)]
@@ -160,33 +630,297 @@ mod test {
     use super::*;
 
     #[test]
-    #[should_panic]
-    fn rename_file_panic_test() {
-        let input = "test_files/invalid_input.csv";
-        rename_files(&input).unwrap();
+    fn check_renaming_test() {
+        assert!(check_renaming(3, 0).is_ok());
+        assert!(matches!(check_renaming(2, 1), Err(Error::Partial { done: 2, failed: 1 })));
+    }
+
+    // The second file is missing. Aborting restores the first one.
+    #[test]
+    fn run_steps_abort_rollback_test() {
+        let dir = std::env::temp_dir().join("renamer_abort_rollback_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let names: Vec<PathBuf> = ["a", "b", "x", "y"].iter()
+            .map(|f| dir.join(format!("{}.fq.gz", f)))
+            .collect();
+        fs::File::create(&names[0]).unwrap();
+        let steps: Vec<Step> = (0..2)
+            .map(|i| Step {
+                origin: names[i].to_path_buf(),
+                destination: names[i + 2].to_path_buf(),
+                temporary: false,
+            })
+            .collect();
+
+        let input = dir.join("input.csv");
+        let mut journal = Journal::create(&input.to_string_lossy(), &RunOpts::default()).unwrap();
+        let mut done = Vec::new();
+        let skip = RunOpts { on_error: OnError::Skip, ..RunOpts::default() };
+        let mut res = Summary::default();
+        run_steps(&steps, &mut journal, &mut done, &skip, &mut res).unwrap();
+        assert_eq!((1, 1), (res.renamed, res.failed));
+        assert!(names[2].is_file());
+
+        fs::rename(&names[2], &names[0]).unwrap();
+        let mut done = Vec::new();
+        let abort = RunOpts { on_error: OnError::AbortRollback, ..RunOpts::default() };
+        let mut res = Summary::default();
+        let err = run_steps(&steps, &mut journal, &mut done, &abort, &mut res).unwrap_err();
+        assert!(matches!(err, Error::Aborted));
+        let statuses: Vec<Status> = res.outcomes.iter().map(|o| o.status).collect();
+        assert_eq!(vec![Status::RolledBack, Status::Skipped], statuses);
+        assert!(names[0].is_file());
+        assert!(!names[2].exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Taken names are numbered or backed up, never overwritten silently.
+    #[test]
+    fn run_steps_on_conflict_test() {
+        let dir = std::env::temp_dir().join("renamer_on_conflict_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.csv");
+        let mut journal = Journal::create(&input.to_string_lossy(), &RunOpts::default()).unwrap();
+        let step = |old: &str| Step {
+            origin: dir.join(old),
+            destination: dir.join("new.fq"),
+            temporary: false,
+        };
+        fs::write(dir.join("new.fq"), "taken").unwrap();
+        fs::write(dir.join("a.fq"), "a").unwrap();
+        fs::write(dir.join("b.fq"), "b").unwrap();
+
+        let mut done = Vec::new();
+        let number = RunOpts { on_conflict: OnConflict::Number, ..RunOpts::default() };
+        let mut res = Summary::default();
+        run_steps(&[step("a.fq")], &mut journal, &mut done, &number, &mut res).unwrap();
+        assert_eq!("a", fs::read_to_string(dir.join("new_1.fq")).unwrap());
+        assert_eq!(1, res.conflicts.len());
+
+        let backup = RunOpts { on_conflict: OnConflict::Backup, ..RunOpts::default() };
+        let mut res = Summary::default();
+        run_steps(&[step("b.fq")], &mut journal, &mut done, &backup, &mut res).unwrap();
+        assert_eq!("b", fs::read_to_string(dir.join("new.fq")).unwrap());
+        assert_eq!("taken", fs::read_to_string(dir.join("new.fq.bak")).unwrap());
+        assert_eq!((1, 0), (res.renamed, res.failed));
+
+        let steps = [step("new_1.fq")];
+        assert!(matches!(check_conflicts(&steps, Mode::Rename), Err(Error::Conflicts(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // A directory holding the new name is left alone and the run goes on.
+    // The file set aside is journaled before it moves.
+    #[test]
+    fn run_steps_overwrite_test() {
+        let dir = std::env::temp_dir().join("renamer_overwrite_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(dir.join("x.fq")).unwrap();
+        fs::write(dir.join("a.fq"), "a").unwrap();
+        fs::write(dir.join("b.fq"), "b").unwrap();
+        fs::write(dir.join("y.fq"), "taken").unwrap();
+        let steps: Vec<Step> = [("a.fq", "x.fq"), ("b.fq", "y.fq")].iter()
+            .map(|(old, new)| Step {
+                origin: dir.join(old),
+                destination: dir.join(new),
+                temporary: false,
+            })
+            .collect();
+
+        let input = dir.join("input.csv");
+        let mut journal = Journal::create(&input.to_string_lossy(), &RunOpts::default()).unwrap();
+        let mut done = Vec::new();
+        let overwrite = RunOpts { on_conflict: OnConflict::Overwrite, ..RunOpts::default() };
+        let mut res = Summary::default();
+        run_steps(&steps, &mut journal, &mut done, &overwrite, &mut res).unwrap();
+        journal.finish().unwrap();
+
+        assert_eq!((1, 1), (res.renamed, res.failed));
+        assert!(dir.join("x.fq").is_dir());
+        assert!(dir.join("a.fq").is_file());
+        assert_eq!("b", fs::read_to_string(dir.join("y.fq")).unwrap());
+        assert!(!dir.join(".renamer-old1_y.fq").exists());
+        let statuses: Vec<Status> = journal::read_journal(&journal.path().to_string_lossy()).unwrap()
+            .records.iter().map(|r| r.status).collect();
+        assert_eq!(vec![
+            Status::Skipped, Status::PendingAside, Status::SetAside,
+            Status::Pending, Status::Renamed, Status::Overwritten,
+        ], statuses);
+        assert_eq!(1, get_done_records(&journal::read_journal(&journal.path().to_string_lossy())
+            .unwrap().records).len());
+        fs::remove_dir_all(&dir).unwrap();
     }
 
+    // The companion follows the numbered name of its file,
+    // and is skipped with a file that is missing.
     #[test]
-    fn create_duplicat_name_test() {
-        let fname = PathBuf::from("data/some_input.fastq.gz");
-        let fname_fq = PathBuf::from("data/some_input.fq.gz");
+    fn run_steps_companions_test() {
+        let dir = std::env::temp_dir().join("renamer_run_companions_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        ["a.fq", "a.fq.fai", "b.fq.fai", "x.fq"].iter()
+            .for_each(|f| { fs::File::create(dir.join(f)).unwrap(); });
+        let steps: Vec<Step> = [("a.fq", "x.fq"), ("a.fq.fai", "x.fq.fai"), ("b.fq", "y.fq"), ("b.fq.fai", "y.fq.fai")]
+            .iter()
+            .map(|(old, new)| Step {
+                origin: dir.join(old),
+                destination: dir.join(new),
+                temporary: false,
+            })
+            .collect();
+
+        let input = dir.join("input.csv");
+        let mut journal = Journal::create(&input.to_string_lossy(), &RunOpts::default()).unwrap();
+        let mut done = Vec::new();
+        let run = RunOpts {
+            companions: true,
+            on_conflict: OnConflict::Number,
+            on_error: OnError::Skip,
+            ..RunOpts::default()
+        };
+        let mut res = Summary::default();
+        run_steps(&steps, &mut journal, &mut done, &run, &mut res).unwrap();
+
+        assert_eq!((2, 2), (res.renamed, res.failed));
+        assert!(dir.join("x_1.fq").is_file());
+        assert!(dir.join("x_1.fq.fai").is_file());
+        assert!(!dir.join("x.fq.fai").exists());
+        assert!(dir.join("b.fq.fai").is_file());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // The taken name is renamed away in the batch, spelled as an absolute path.
+    #[test]
+    fn check_conflicts_mixed_paths_test() {
+        let taken = PathBuf::from("test_files/valid.fastq.gz");
+        let steps = [
+            Step {
+                origin: PathBuf::from("test_files/a.fq"),
+                destination: taken.to_path_buf(),
+                temporary: false,
+            },
+            Step {
+                origin: std::env::current_dir().unwrap().join(&taken),
+                destination: PathBuf::from("test_files/b.fq"),
+                temporary: false,
+            },
+        ];
 
-        let res = PathBuf::from("data/some_input_renamerdup.fastq.gz");
-        let res_fq = PathBuf::from("data/some_input_renamerdup.fq.gz");
-        assert_eq!(res, create_duplicate_names(&fname));
-        assert_eq!(res_fq, create_duplicate_names(&fname_fq));
+        assert!(check_conflicts(&steps, Mode::Rename).is_ok());
+        assert!(check_conflicts(&steps, Mode::Copy).is_err());
     }
 
     #[test]
-    fn check_duplicate_test() {
-        let prop_name = PathBuf::from("test_files/valid.fastq.gz"); // exist in test_files dir
-        let prop_fq = PathBuf::from("test_files/valid2.fq.gz"); // exist in test_files dir
+    fn rename_files_move_test() {
+        let dir = std::env::temp_dir().join("renamer_move_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(dir.join("raw")).unwrap();
+        fs::File::create(dir.join("raw/a.fq.gz")).unwrap();
+        let input = dir.join("input.csv");
+        let contents = format!("old_names,new_names\n{},project_a/run_1/x.fq.gz\n",
+            dir.join("raw/a.fq.gz").display());
+        fs::write(&input, contents).unwrap();
+
+        let opts = InputOpts { dest_root: Some(dir.to_path_buf()), ..InputOpts::default() };
+        let run = RunOpts { on_error: OnError::Skip, ..RunOpts::default() };
+        rename_files(&input.to_string_lossy(), &opts, &run).unwrap();
+
+        assert!(dir.join("project_a/run_1/x.fq.gz").is_file());
+        assert!(!dir.join("raw/a.fq.gz").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
-        let new_names = PathBuf::from("test_files/valid_renamerdup.fastq.gz");
-        let new_fq = PathBuf::from("test_files/valid2_renamerdup.fq.gz");
+    // Copies are counted and journaled as in rename mode.
+    // Undo removes the copies and leaves the originals.
+    #[test]
+    fn rename_files_copy_mode_test() {
+        let dir = std::env::temp_dir().join("renamer_copy_mode_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let names: Vec<PathBuf> = ["a", "b", "x", "y"].iter()
+            .map(|f| dir.join(format!("{}.fq.gz", f)))
+            .collect();
+        fs::write(&names[0], "ACGT").unwrap();
+        fs::write(&names[1], "TGCA").unwrap();
+        let input = dir.join("input.csv");
+        let contents = format!("old_names,new_names\n{},x.fq.gz\n{},y.fq.gz\n",
+            names[0].display(), names[1].display());
+        fs::write(&input, contents).unwrap();
+
+        let run = RunOpts { mode: Mode::Copy, on_error: OnError::Skip, ..RunOpts::default() };
+        rename_files(&input.to_string_lossy(), &InputOpts::default(), &run).unwrap();
+        assert!(names.iter().all(|f| f.is_file()));
+        assert_eq!("TGCA", fs::read_to_string(&names[3]).unwrap());
+
+        let journal = fs::read_dir(&dir).unwrap()
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .find(|p| p.to_string_lossy().ends_with(".tsv"))
+            .unwrap();
+        let log = journal::read_journal(&journal.to_string_lossy()).unwrap();
+        assert_eq!(Mode::Copy, log.mode);
+        assert_eq!(2, log.records.iter().filter(|r| r.status == Status::Copied).count());
+
+        undo_files(&journal.to_string_lossy()).unwrap();
+        assert!(names[..2].iter().all(|f| f.is_file()));
+        assert!(names[2..].iter().all(|f| !f.exists()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
 
-        assert_eq!(new_names, check_new_names(&prop_name));
-        assert_eq!(new_fq, check_new_names(&prop_fq));
+    #[test]
+    fn rename_file_err_test() {
+        let input = "test_files/invalid_input.csv";
+        assert!(rename_files(input, &InputOpts::default(), &RunOpts::default()).is_err());
+    }
+
+    #[test]
+    fn undo_files_test() {
+        let dir = std::env::temp_dir().join("renamer_undo_files_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.csv");
+        let old = dir.join("old.fq.gz");
+        let new = dir.join("new.fq.gz");
+        let taken = dir.join("taken.fq.gz");
+        let moved = dir.join("moved.fq.gz");
+        fs::File::create(&new).unwrap();
+        fs::File::create(&taken).unwrap();
+        fs::File::create(&moved).unwrap();
+
+        let mut journal = Journal::create(&input.to_string_lossy(), &RunOpts::default()).unwrap();
+        journal.record(Status::Renamed, &old, &new).unwrap();
+        journal.record(Status::Renamed, &taken, &moved).unwrap();
+        let path = journal.path().to_string_lossy().into_owned();
+        assert!(undo_files(&path).is_err()); // Unfinished journal.
+
+        journal.finish().unwrap();
+        let err = undo_files(&path).unwrap_err(); // One file can't be restored.
+        assert_eq!(crate::error::PARTIAL_RENAME, err.exit_code());
+
+        assert!(old.is_file());
+        assert!(!new.exists());
+        assert!(moved.is_file()); // Origin is taken, left as it is.
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn get_done_records_test() {
+        let record = |status, origin: &str, destination: &str| Record {
+            status,
+            origin: PathBuf::from(origin),
+            destination: PathBuf::from(destination),
+        };
+        let records = vec![
+            record(Status::Renamed, "a.fq", "b.fq"),
+            record(Status::Renamed, "c.fq", "d.fq"),
+            record(Status::Skipped, "e.fq", "f.fq"),
+            record(Status::RolledBack, "c.fq", "d.fq"),
+        ];
+        let done = get_done_records(&records);
+        assert_eq!(1, done.len());
+        assert_eq!(Path::new("b.fq"), done[0].destination);
     }
 
 }
\ No newline at end of file
@@ -0,0 +1,131 @@
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::checksum;
+use crate::companion;
+use crate::error::Error;
+use crate::extension;
+use crate::finder;
+use crate::journal;
+use crate::parser;
+use crate::plan::RenamePlan;
+use crate::policy::RunOpts;
+use crate::renamer;
+
+// Options for renaming with a regular expression.
+// The pattern applies to the file name without its extension,
+// so the extension is kept, as for the rename table.
+// Replace may use capture groups, such as $1 or ${lane}.
+// Extension limits the files, as in the find subcommand.
+#[derive(Debug)]
+pub struct RegexOpts {
+    pub dir: String,
+    pub ext: Option<String>,
+    pub pattern: Regex,
+    pub replace: String,
+}
+
+// Builds the rename plan from the files matching the pattern,
+// then checks and renames it the same way as a rename table.
+// The journal is saved in the directory being renamed.
+pub fn rename_files(opts: &RegexOpts, run: &RunOpts) -> Result<(), Error> {
+    info!("Checking input...");
+    let mut plan = build_plan(opts)?;
+    if plan.len() == 0 {
+        info!("No files match {}", opts.pattern);
+        return Ok(());
+    }
+
+    if run.companions {
+        companion::add_companions(&mut plan)?;
+    }
+
+    let checks = parser::check_plan(&opts.dir, &plan, run)?;
+    if run.dryrun {
+        return Ok(());
+    }
+
+    renamer::rename_plan(&opts.dir, &plan, &checks, run)
+}
+
+// Files whose names don't change are left out.
+fn build_plan(opts: &RegexOpts) -> Result<RenamePlan, Error> {
+    let files = finder::find_files(&opts.dir, opts.ext.as_deref())
+        .into_iter()
+        .filter(|f| !journal::is_journal(f) && !checksum::is_manifest(f))
+        .filter_map(|f| replace_names(&f, opts).map(|new_names| (f, new_names)));
+
+    RenamePlan::from_files(files)
+}
+
+// None if the pattern doesn't change the name.
+// Replacements that empty the name or add a directory are errors.
+fn replace_names(path: &Path, opts: &RegexOpts) -> Option<Result<PathBuf, Error>> {
+    let (stem, ext) = extension::split_file_names(path);
+    let new_stem = opts.pattern.replace_all(&stem, opts.replace.as_str());
+    if new_stem == stem {
+        return None;
+    }
+
+    let invalid_name = |msg: String| Error::InvalidInput { path: path.to_path_buf(), msg };
+    if new_stem.is_empty() {
+        return Some(Err(invalid_name(String::from("THE REPLACEMENT LEAVES NO FILE NAME."))));
+    }
+
+    if new_stem.contains('/') || new_stem.contains('\\') {
+        return Some(Err(invalid_name(format!("THE NEW NAME {}{} CONTAINS A PATH SEPARATOR.", new_stem, ext))));
+    }
+
+    Some(Ok(path.with_file_name(format!("{}{}", new_stem, ext))))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn regex_opts(dir: &Path, pattern: &str, replace: &str) -> RegexOpts {
+        RegexOpts {
+            dir: dir.to_string_lossy().into_owned(),
+            ext: None,
+            pattern: Regex::new(pattern).unwrap(),
+            replace: String::from(replace),
+        }
+    }
+
+    #[test]
+    fn replace_names_test() {
+        let opts = regex_opts(Path::new("data"), r"_L(\d+)_001$", "_lane$1");
+        let res = replace_names(Path::new("data/a_L2_001.fastq.gz"), &opts).unwrap().unwrap();
+        assert_eq!(PathBuf::from("data/a_lane2.fastq.gz"), res);
+        assert!(replace_names(Path::new("data/a_R1.fastq.gz"), &opts).is_none());
+
+        let opts = regex_opts(Path::new("data"), "-", "_");
+        let res = replace_names(Path::new("data/a-b-c.fq.gz"), &opts).unwrap().unwrap();
+        assert_eq!(PathBuf::from("data/a_b_c.fq.gz"), res);
+
+        let opts = regex_opts(Path::new("data"), ".*", "");
+        assert!(replace_names(Path::new("data/a.fq"), &opts).unwrap().is_err());
+    }
+
+    #[test]
+    fn build_plan_test() {
+        let dir = env::temp_dir().join("renamer_regex_plan_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(dir.join("raw")).unwrap();
+        ["a_001.fq.gz", "b_001.fq.gz", "raw/c_001.fq.gz", "d.fq.gz", "renamer-journal_001.tsv",
+            "renamer-manifest_001.sha256"].iter()
+            .for_each(|f| { fs::File::create(dir.join(f)).unwrap(); });
+
+        let plan = build_plan(&regex_opts(&dir, "_001$", "")).unwrap();
+        assert_eq!(3, plan.len());
+        assert_eq!(dir.join("a.fq.gz"), plan.entries()[0].destination);
+        assert_eq!(dir.join("raw/c.fq.gz"), plan.entries()[2].destination);
+
+        let err = build_plan(&regex_opts(&dir, r"^\w_001$", "x")).unwrap_err();
+        assert!(matches!(err, Error::Table(ref e) if e.len() == 1));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
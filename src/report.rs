@@ -0,0 +1,323 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use clap::crate_version;
+use serde_json::json;
+
+use crate::checker::{self, Checked};
+use crate::error::Error;
+use crate::plan::{self, RenamePlan};
+use crate::policy::RunOpts;
+use crate::renamer::Outcome;
+use crate::transfer;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+impl Format {
+    // The format follows the extension of the report file.
+    pub fn from_path(path: &str) -> Result<Self, String> {
+        let ext = Path::new(path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+
+        match ext.as_deref() {
+            Some("json") => Ok(Format::Json),
+            Some("csv") => Ok(Format::Csv),
+            _ => Err(format!("Invalid report file: {}. Use a .json or .csv file.", path)),
+        }
+    }
+}
+
+// A row of the report for each row of the input.
+// Status is the input check in a dry run (ok, new-dir, chain, or error),
+// and the journal status after a run (renamed, skipped, not-found, and so on).
+// Rows never reached before an abort are not-attempted.
+// New name is the name the file got. It differs from the destination
+// when the proposed name was taken and renamed automatically.
+// Resolution explains what was done with a taken name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportRow {
+    pub line: usize,
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub new_names: Option<PathBuf>,
+    pub status: String,
+    pub error: Option<usize>,
+    pub note: Option<String>,
+    pub resolution: Option<String>,
+}
+
+// Writes the input check, when asked for a report.
+pub fn report_checks(input: &str, plan: &RenamePlan, checks: &[Checked], run: &RunOpts) -> Result<(), Error> {
+    match &run.report {
+        Some(path) => write_report(path, input, run, &get_checked_rows(plan, checks)),
+        None => Ok(()),
+    }
+}
+
+// Writes the run, when asked for a report.
+// After an abort, the outcomes are the steps reached,
+// with the files put back marked as rolled back.
+pub fn report_run(
+    input: &str,
+    plan: &RenamePlan,
+    checks: &[Checked],
+    outcomes: &[Outcome],
+    run: &RunOpts
+) -> Result<(), Error> {
+    let path = match &run.report {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let mut rows = get_checked_rows(plan, checks);
+    add_outcomes(&mut rows, outcomes);
+    write_report(path, input, run, &rows)
+}
+
+fn get_checked_rows(plan: &RenamePlan, checks: &[Checked]) -> Vec<ReportRow> {
+    plan.entries()
+        .iter()
+        .zip(checks.iter())
+        .map(|(e, c)| ReportRow {
+            line: e.line,
+            source: e.origin.to_path_buf(),
+            destination: e.destination.to_path_buf(),
+            new_names: None,
+            status: String::from(c.status),
+            error: c.error,
+            note: c.note.clone(),
+            resolution: None,
+        })
+        .collect()
+}
+
+// Rows without a step kept their names.
+// Rows with a step but no outcome were not reached.
+// Only files that were renamed get a new name.
+fn add_outcomes(rows: &mut [ReportRow], outcomes: &[Outcome]) {
+    let outcomes: HashMap<&Path, &Outcome> = outcomes.iter()
+        .map(|o| (o.destination.as_path(), o))
+        .collect();
+    rows.iter_mut().for_each(|row| {
+        match outcomes.get(row.destination.as_path()) {
+            Some(outcome) => {
+                row.status = String::from(outcome.status.as_str());
+                if outcome.status.is_done() {
+                    row.new_names = Some(outcome.new_names.to_path_buf());
+                }
+                row.resolution = outcome.resolution.clone();
+            }
+            None if plan::get_key(&row.source) == plan::get_key(&row.destination) => {
+                row.status = String::from("unchanged")
+            }
+            None => row.status = String::from("not-attempted"),
+        }
+    });
+}
+
+// Metadata identifies the run: renamer version, time, and the input file
+// with its sha256 checksum, so the report can be matched to the input.
+fn write_report(path: &Path, input: &str, run: &RunOpts, rows: &[ReportRow]) -> Result<(), Error> {
+    let format = Format::from_path(&path.to_string_lossy())
+        .map_err(|msg| Error::InvalidInput { path: path.to_path_buf(), msg })?;
+    let meta = Metadata::new(input, run);
+
+    match format {
+        Format::Json => write_json(path, &meta, rows),
+        Format::Csv => write_csv(path, &meta, rows),
+    }
+    .map_err(Error::io(path))?;
+
+    info!("The report is saved as {}", path.display());
+    Ok(())
+}
+
+struct Metadata {
+    version: String,
+    date: String,
+    input: String,
+    input_sha256: Option<String>,
+    workdir: String,
+    mode: String,
+    dryrun: bool,
+}
+
+impl Metadata {
+    // Inputs of the regex and sanitize subcommands are directories,
+    // so they have no checksum.
+    fn new(input: &str, run: &RunOpts) -> Self {
+        let input_path = Path::new(input);
+        let input_sha256 = match input_path.is_file() {
+            true => transfer::compute_checksum(input_path).ok().map(|(_, checksum)| checksum),
+            false => None,
+        };
+
+        Self {
+            version: String::from(crate_version!()),
+            date: Local::now().to_rfc3339(),
+            input: input.to_string(),
+            input_sha256,
+            workdir: env::current_dir().map(|d| d.display().to_string()).unwrap_or_default(),
+            mode: String::from(run.mode.as_str()),
+            dryrun: run.dryrun,
+        }
+    }
+}
+
+fn write_json(path: &Path, meta: &Metadata, rows: &[ReportRow]) -> io::Result<()> {
+    let rows: Vec<_> = rows.iter()
+        .map(|r| json!({
+            "line": r.line,
+            "source": r.source.to_string_lossy(),
+            "destination": r.destination.to_string_lossy(),
+            "new_name": r.new_names.as_ref().map(|p| p.to_string_lossy()),
+            "status": r.status,
+            "error_kind": r.error,
+            "error": r.error.map(checker::describe_error),
+            "note": r.note,
+            "resolution": r.resolution,
+        }))
+        .collect();
+
+    let report = json!({
+        "renamer": meta.version,
+        "date": meta.date,
+        "input": meta.input,
+        "input_sha256": meta.input_sha256,
+        "workdir": meta.workdir,
+        "mode": meta.mode,
+        "dry_run": meta.dryrun,
+        "rows": rows,
+    });
+
+    let mut file = File::create(path)?;
+    serde_json::to_writer_pretty(&mut file, &report)?;
+    writeln!(file)
+}
+
+// Metadata lines start with '#', as in the journal.
+fn write_csv(path: &Path, meta: &Metadata, rows: &[ReportRow]) -> io::Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_path(path)?;
+
+    writer.write_record(["#renamer", &meta.version])?;
+    writer.write_record(["#date", &meta.date])?;
+    writer.write_record(["#input", &meta.input])?;
+    writer.write_record(["#input_sha256", meta.input_sha256.as_deref().unwrap_or_default()])?;
+    writer.write_record(["#workdir", &meta.workdir])?;
+    writer.write_record(["#mode", &meta.mode])?;
+    writer.write_record(["#dry_run", &meta.dryrun.to_string()])?;
+    writer.write_record([
+        "line", "source", "destination", "new_name", "status", "error_kind", "error", "note", "resolution"
+    ])?;
+
+    for r in rows {
+        writer.write_record([
+            r.line.to_string(),
+            r.source.to_string_lossy().into_owned(),
+            r.destination.to_string_lossy().into_owned(),
+            r.new_names.as_ref().map(|p| p.to_string_lossy().into_owned()).unwrap_or_default(),
+            r.status.to_string(),
+            r.error.map(|e| e.to_string()).unwrap_or_default(),
+            r.error.map(checker::describe_error).unwrap_or_default().to_string(),
+            r.note.clone().unwrap_or_default(),
+            r.resolution.clone().unwrap_or_default(),
+        ])?;
+    }
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use crate::journal::Status;
+
+    fn report_rows() -> Vec<ReportRow> {
+        vec![ReportRow {
+            line: 2,
+            source: PathBuf::from("raw/a, b.fq"),
+            destination: PathBuf::from("raw/x.fq"),
+            new_names: Some(PathBuf::from("raw/x_renamerdup.fq")),
+            status: String::from("renamed"),
+            error: Some(3),
+            note: None,
+            resolution: Some(String::from("renamed to raw/x_renamerdup.fq")),
+        }]
+    }
+
+    #[test]
+    fn format_from_path_test() {
+        assert_eq!(Ok(Format::Json), Format::from_path("run.JSON"));
+        assert_eq!(Ok(Format::Csv), Format::from_path("reports/run.csv"));
+        assert!(Format::from_path("run.txt").is_err());
+    }
+
+    #[test]
+    fn add_outcomes_test() {
+        let mut rows = report_rows();
+        rows.push(ReportRow { line: 3, source: PathBuf::from("raw/b.fq"), destination: PathBuf::from("raw/y.fq"), ..rows[0].clone() });
+        rows.iter_mut().for_each(|r| { r.status = String::from("ok"); r.new_names = None; });
+        rows.push(ReportRow { line: 4, source: PathBuf::from("raw/z.fq"), destination: PathBuf::from("raw/z.fq"), ..rows[1].clone() });
+        let outcomes = vec![Outcome {
+            destination: PathBuf::from("raw/x.fq"),
+            new_names: PathBuf::from("raw/x_1.fq"),
+            status: Status::Renamed,
+            resolution: Some(String::from("renamed")),
+        }];
+
+        add_outcomes(&mut rows, &outcomes);
+        assert_eq!("renamed", rows[0].status);
+        assert_eq!(Some(PathBuf::from("raw/x_1.fq")), rows[0].new_names);
+        assert_eq!("not-attempted", rows[1].status);
+        assert_eq!(None, rows[1].new_names);
+        assert_eq!("unchanged", rows[2].status);
+
+        let rolled_back = vec![Outcome { status: Status::RolledBack, ..outcomes[0].clone() }];
+        add_outcomes(&mut rows[..1], &rolled_back);
+        assert_eq!("rolled-back", rows[0].status);
+    }
+
+    #[test]
+    fn write_json_report_test() {
+        let dir = env::temp_dir().join("renamer_report_json_test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("report.json");
+        write_report(&path, "test_files/input.csv", &RunOpts::default(), &report_rows()).unwrap();
+
+        let report: serde_json::Value = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        assert_eq!(crate_version!(), report["renamer"]);
+        assert_eq!(64, report["input_sha256"].as_str().unwrap().len());
+        assert_eq!("raw/a, b.fq", report["rows"][0]["source"]);
+        assert_eq!("raw/x_renamerdup.fq", report["rows"][0]["new_name"]);
+        assert_eq!(3, report["rows"][0]["error_kind"]);
+        assert!(report["rows"][0]["note"].is_null());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_csv_report_test() {
+        let dir = env::temp_dir().join("renamer_report_csv_test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("report.csv");
+        write_report(&path, "raw", &RunOpts::default(), &report_rows()).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!("#input_sha256,", lines[3]);
+        assert!(lines[7].starts_with("line,source,destination"));
+        assert!(lines[8].starts_with("2,\"raw/a, b.fq\",raw/x.fq,raw/x_renamerdup.fq,renamed,3,"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,263 @@
+use std::path::{Path, PathBuf};
+
+use deunicode::deunicode;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::checksum;
+use crate::companion;
+use crate::error::Error;
+use crate::extension;
+use crate::finder;
+use crate::journal;
+use crate::output;
+use crate::parser;
+use crate::plan::RenamePlan;
+use crate::policy::RunOpts;
+use crate::renamer;
+use crate::writer;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Case {
+    #[default]
+    Keep,
+    Lower,
+    Upper,
+}
+
+impl Case {
+    pub fn parse(case: &str) -> Result<Self, String> {
+        match case {
+            "keep" => Ok(Case::Keep),
+            "lower" => Ok(Case::Lower),
+            "upper" => Ok(Case::Upper),
+            c => Err(format!("Invalid case: {}. Use keep, lower, or upper.", c)),
+        }
+    }
+}
+
+// How names are made safe for shell tools.
+// Letters and digits are always allowed. Allowed lists the other characters kept.
+// Everything else becomes the replacement character.
+// Transliteration turns non-ASCII characters into ASCII, such as é to e.
+// NFC keeps non-ASCII letters and digits instead,
+// composed the same way whichever system wrote them.
+// Collapse turns runs of separators into one
+// and trims them from both ends of the name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rules {
+    pub allowed: String,
+    pub replacement: char,
+    pub case: Case,
+    pub nfc: bool,
+    pub transliterate: bool,
+    pub collapse: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            allowed: String::from("._-"),
+            replacement: '_',
+            case: Case::Keep,
+            nfc: false,
+            transliterate: false,
+            collapse: false,
+        }
+    }
+}
+
+// Without apply, the proposed names are written to the output csv for review.
+#[derive(Debug)]
+pub struct SanitizeOpts {
+    pub dir: String,
+    pub ext: Option<String>,
+    pub output: String,
+    pub apply: bool,
+    pub rules: Rules,
+}
+
+pub fn sanitize_files(opts: &SanitizeOpts, run: &RunOpts) -> Result<(), Error> {
+    let names = propose_names(opts);
+    info!("Found {} files to sanitize", names.len());
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    if !opts.apply {
+        return writer::write_names_to_csv(&opts.output, &names);
+    }
+
+    info!("Checking input...");
+    let mut plan = build_plan(&names)?;
+    if run.companions {
+        companion::add_companions(&mut plan)?;
+    }
+    let checks = parser::check_plan(&opts.dir, &plan, run)?;
+    if run.dryrun {
+        return Ok(());
+    }
+
+    renamer::rename_plan(&opts.dir, &plan, &checks, run)
+}
+
+// Only files whose names change are proposed.
+// Hidden files are left alone, so are names with nothing safe left.
+fn propose_names(opts: &SanitizeOpts) -> Vec<(PathBuf, String)> {
+    finder::find_files(&opts.dir, opts.ext.as_deref())
+        .into_iter()
+        .filter(|f| !journal::is_journal(f) && !checksum::is_manifest(f))
+        .filter(|f| !f.file_name().unwrap_or_default().to_string_lossy().starts_with('.'))
+        .filter_map(|f| {
+            let fname = f.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            match sanitize_names(&f, &opts.rules) {
+                Some(new_names) if new_names != fname => Some((f, new_names)),
+                Some(_) => None,
+                None => {
+                    warn!("{} Skipping it...", output::yellow(format!("NOTHING SAFE LEFT IN {:?}.", f)));
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+fn build_plan(names: &[(PathBuf, String)]) -> Result<RenamePlan, Error> {
+    let files = names.iter()
+        .map(|(path, new_names)| (path.to_path_buf(), Ok(path.with_file_name(new_names))));
+
+    RenamePlan::from_files(files)
+}
+
+// The stem and each part of the extension are sanitized apart,
+// so the dots of the extension are always kept.
+// None if the stem has nothing safe left.
+pub fn sanitize_names(path: &Path, rules: &Rules) -> Option<String> {
+    let (stem, ext) = extension::split_file_names(path);
+    let stem = sanitize_part(&stem, rules);
+    if stem.is_empty() {
+        return None;
+    }
+
+    let ext: String = ext.split('.')
+        .skip(1)
+        .map(|part| format!(".{}", sanitize_part(part, rules)))
+        .collect();
+
+    Some(format!("{}{}", stem, ext))
+}
+
+fn sanitize_part(part: &str, rules: &Rules) -> String {
+    let part = if rules.transliterate {
+        deunicode(part)
+    } else if rules.nfc {
+        part.nfc().collect()
+    } else {
+        part.to_string()
+    };
+
+    let part = match rules.case {
+        Case::Keep => part,
+        Case::Lower => part.to_lowercase(),
+        Case::Upper => part.to_uppercase(),
+    };
+
+    let safe: String = part.chars()
+        .map(|c| if is_allowed(c, rules) { c } else { rules.replacement })
+        .collect();
+
+    if rules.collapse {
+        collapse_separators(&safe, rules)
+    } else {
+        safe
+    }
+}
+
+fn is_allowed(c: char, rules: &Rules) -> bool {
+    c.is_ascii_alphanumeric()
+        || (rules.nfc && !rules.transliterate && c.is_alphanumeric())
+        || rules.allowed.contains(c)
+}
+
+// Separators are the replacement and the allowed characters
+// that are not letters or digits. A run keeps its first character.
+fn collapse_separators(name: &str, rules: &Rules) -> String {
+    let is_separator = |c: char| c == rules.replacement || (!c.is_alphanumeric() && rules.allowed.contains(c));
+    let mut collapsed = String::new();
+    let mut prev_sep = false;
+    for c in name.chars() {
+        let sep = is_separator(c);
+        if !(sep && prev_sep) {
+            collapsed.push(c);
+        }
+        prev_sep = sep;
+    }
+
+    collapsed.trim_matches(is_separator).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn sanitize(name: &str, rules: &Rules) -> Option<String> {
+        sanitize_names(Path::new(name), rules)
+    }
+
+    #[test]
+    fn sanitize_names_test() {
+        let rules = Rules::default();
+        assert_eq!(Some(String::from("Bunomys_andrewsi__1_.fastq.gz")), sanitize("Bunomys andrewsi (1).fastq.gz", &rules));
+        assert_eq!(Some(String::from("sample.R1.fq")), sanitize("sample.R1.fq", &rules));
+        assert_eq!(Some(String::from("M_ller.fq.gz")), sanitize("Müller.fq.gz", &rules));
+        assert_eq!(None, sanitize("(((.txt", &Rules { collapse: true, ..Rules::default() }));
+    }
+
+    #[test]
+    fn sanitize_names_rules_test() {
+        let rules = Rules {
+            case: Case::Lower,
+            transliterate: true,
+            collapse: true,
+            ..Rules::default()
+        };
+        assert_eq!(Some(String::from("bunomys_muller_1.fastq.gz")), sanitize("Bunomys  Müller (1).FASTQ.GZ", &rules));
+
+        let rules = Rules { nfc: true, replacement: '-', ..Rules::default() };
+        assert_eq!(Some(String::from("M\u{fc}ller-1.fq")), sanitize("Mu\u{308}ller 1.fq", &rules));
+
+        let rules = Rules { allowed: String::from("."), collapse: true, ..Rules::default() };
+        assert_eq!(Some(String::from("a_b.c.fq")), sanitize("a-_-b..c.fq", &rules));
+    }
+
+    #[test]
+    fn parse_case_test() {
+        assert_eq!(Ok(Case::Lower), Case::parse("lower"));
+        assert!(Case::parse("title").is_err());
+    }
+
+    #[test]
+    fn propose_names_test() {
+        let dir = env::temp_dir().join("renamer_sanitize_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        ["a b.fq", "safe.fq", ".hidden file", "c (1).fq"].iter()
+            .for_each(|f| { fs::File::create(dir.join(f)).unwrap(); });
+        let opts = SanitizeOpts {
+            dir: dir.to_string_lossy().into_owned(),
+            ext: None,
+            output: String::new(),
+            apply: true,
+            rules: Rules { collapse: true, ..Rules::default() },
+        };
+
+        let names = propose_names(&opts);
+        assert_eq!(vec![
+            (dir.join("a b.fq"), String::from("a_b.fq")),
+            (dir.join("c (1).fq"), String::from("c_1.fq")),
+        ], names);
+        assert_eq!(2, build_plan(&names).unwrap().len());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
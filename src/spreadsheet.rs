@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+
+use calamine::{open_workbook_auto, Data, Range, Reader};
+use csv::StringRecord;
+
+use crate::error::Error;
+use crate::parser::{InputOpts, Table};
+
+pub fn is_spreadsheet(path: &str) -> bool {
+    let ext = Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+
+    matches!(ext.as_deref(), Some("xlsx") | Some("xlsm") | Some("xlsb") | Some("xls") | Some("ods"))
+}
+
+// Reads the rename table straight from the spreadsheet,
+// so cell values are read as they are in the workbook.
+// Rows above the header row are ignored.
+// Row numbers follow the spreadsheet, starting from 1.
+pub fn read_spreadsheet(path: &str, opts: &InputOpts) -> Result<Table, Error> {
+    let invalid_input = |msg: String| Error::InvalidInput { path: PathBuf::from(path), msg };
+    let mut workbook = open_workbook_auto(path)
+        .map_err(|e| invalid_input(e.to_string()))?;
+
+    let sheet = match &opts.sheet {
+        Some(sheet) => sheet.to_string(),
+        None => match workbook.sheet_names().first() {
+            Some(sheet) => sheet.to_string(),
+            None => return Err(invalid_input(String::from("NO SHEET FOUND."))),
+        },
+    };
+
+    let range = workbook.worksheet_range(&sheet)
+        .map_err(|e| invalid_input(format!("CAN'T READ SHEET {}. {}", sheet, e)))?;
+
+    split_rows(&range, opts.header_row)
+        .ok_or_else(|| invalid_input(format!("HEADER ROW NOT FOUND IN SHEET {}.", sheet)))
+}
+
+fn split_rows(range: &Range<Data>, header_row: Option<usize>) -> Option<Table> {
+    let first_row = range.start().map_or(0, |(row, _)| row as usize + 1);
+    let first_col = range.start().map_or(0, |(_, col)| col as usize);
+    let header_row = header_row.unwrap_or(first_row);
+
+    let mut headers = None;
+    let mut rows = Vec::new();
+    range.rows()
+        .enumerate()
+        .map(|(i, cells)| (first_row + i, cells))
+        .filter(|(row, _)| *row >= header_row)
+        .for_each(|(row, cells)| {
+            // Keep the columns aligned with the spreadsheet columns.
+            let mut fields = vec![String::new(); first_col];
+            fields.extend(cells.iter().map(|c| c.to_string().trim().to_string()));
+            let recs = StringRecord::from(fields);
+
+            if headers.is_none() {
+                headers = Some(recs);
+            } else if recs.iter().any(|f| !f.is_empty()) {
+                rows.push((row, recs));
+            }
+        });
+
+    headers.map(|headers| Table { headers, rows })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn is_spreadsheet_test() {
+        assert!(is_spreadsheet("data/samples.xlsx"));
+        assert!(is_spreadsheet("data/samples.ODS"));
+        assert!(!is_spreadsheet("data/samples.csv"));
+    }
+
+    #[test]
+    fn read_xlsx_test() {
+        let opts = InputOpts {
+            sheet: Some(String::from("samples")),
+            header_row: Some(2),
+            ..InputOpts::default()
+        };
+        let table = read_spreadsheet("test_files/input.xlsx", &opts).unwrap();
+
+        assert_eq!(StringRecord::from(vec!["old_names", "new_names", "species"]), table.headers);
+        assert_eq!(2, table.rows.len());
+        assert_eq!(3, table.rows[0].0);
+        assert_eq!(Some("valid_new.fastq.gz"), table.rows[0].1.get(1));
+    }
+
+    #[test]
+    fn read_xlsx_sheet_err_test() {
+        let opts = InputOpts {
+            sheet: Some(String::from("plates")),
+            ..InputOpts::default()
+        };
+        let err = read_spreadsheet("test_files/input.xlsx", &opts).unwrap_err();
+
+        assert!(err.to_string().contains("CAN'T READ SHEET plates."));
+    }
+
+    #[test]
+    fn read_xlsx_header_row_err_test() {
+        let opts = InputOpts {
+            header_row: Some(20),
+            ..InputOpts::default()
+        };
+        assert!(read_spreadsheet("test_files/input.xlsx", &opts).is_err());
+    }
+
+    #[test]
+    fn read_ods_test() {
+        let table = read_spreadsheet("test_files/input.ods", &InputOpts::default()).unwrap();
+
+        assert_eq!(StringRecord::from(vec!["old_names", "new_names"]), table.headers);
+        assert_eq!(1, table.rows.len());
+        assert_eq!(2, table.rows[0].0);
+    }
+
+    #[test]
+    fn parse_xlsx_test() {
+        let opts = InputOpts {
+            from: Some(String::from("old_names")),
+            to: Some(String::from("new_names")),
+            sheet: Some(String::from("samples")),
+            header_row: Some(2),
+            ..InputOpts::default()
+        };
+        let dry = crate::policy::RunOpts { dryrun: true, ..Default::default() };
+        let (plan, _) = crate::parser::parse_input("test_files/input.xlsx", &opts, &dry).unwrap();
+
+        assert_eq!(PathBuf::from("test_files/valid.fastq.gz"), plan.entries()[0].origin);
+        assert_eq!(PathBuf::from("test_files/valid_new.fastq.gz"), plan.entries()[0].destination);
+        assert_eq!(4, plan.entries()[1].line);
+    }
+}
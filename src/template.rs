@@ -0,0 +1,232 @@
+use std::path::Path;
+
+use csv::StringRecord;
+
+use crate::error::Error;
+use crate::extension;
+use crate::parser;
+
+// New names built from the columns of the input table,
+// such as {species}_{voucher}_{read}.{ext}.
+// Placeholders are header names or column numbers, starting from 1.
+// Built-ins take precedence over the columns:
+//     {stem}   original file name without its extension
+//     {ext}    original extension without the leading dot, such as fastq.gz
+//     {row}    row counter, starting from 1. {row:3} pads it to 001
+//     {parent} name of the directory of the original file
+// Use {{ and }} for literal braces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Placeholder(String),
+}
+
+// Everything a row needs to fill the template.
+pub struct Row<'a> {
+    pub headers: &'a StringRecord,
+    pub recs: &'a StringRecord,
+    pub old_names: &'a Path,
+    pub counts: usize,
+    pub lcounts: usize,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        match c {
+                            '}' => {
+                                closed = true;
+                                break;
+                            }
+                            c => name.push(c),
+                        }
+                    }
+                    if !closed || name.contains('{') {
+                        return Err(format!("Invalid template: {}. A placeholder is not closed.", template));
+                    }
+                    if name.trim().is_empty() {
+                        return Err(format!("Invalid template: {}. Empty placeholder.", template));
+                    }
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Placeholder(name.trim().to_string()));
+                }
+                '}' => return Err(format!("Invalid template: {}. Unmatched }}.", template)),
+                c => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        if !parts.iter().any(|p| matches!(p, Part::Placeholder(_))) {
+            return Err(format!("Invalid template: {}. No placeholder found.", template));
+        }
+
+        Ok(Self { parts })
+    }
+
+    // Reports every placeholder that is neither a built-in nor a column,
+    // before any row is read.
+    pub fn check_placeholders(&self, headers: &StringRecord) -> Result<(), Error> {
+        let unknown: Vec<Error> = self.placeholders()
+            .filter(|name| !is_builtin(name) && parser::get_column_index(headers, name).is_none())
+            .map(|name| Error::Placeholder(name.to_string()))
+            .collect();
+
+        match unknown.len() {
+            0 => Ok(()),
+            1 => Err(unknown.into_iter().next().unwrap()),
+            _ => Err(Error::Table(unknown)),
+        }
+    }
+
+    // Empty values are reported with the line of the row,
+    // so no file gets a name with missing parts.
+    pub fn fill(&self, row: &Row) -> Result<String, Error> {
+        let mut new_names = String::new();
+        for part in self.parts.iter() {
+            match part {
+                Part::Text(text) => new_names.push_str(text),
+                Part::Placeholder(name) => {
+                    let value = get_value(name, row);
+                    if value.is_empty() {
+                        return Err(Error::InvalidRow {
+                            line: row.lcounts,
+                            msg: format!("EMPTY VALUE FOR {{{}}} IN THE TEMPLATE", name),
+                        });
+                    }
+                    new_names.push_str(&value);
+                }
+            }
+        }
+
+        Ok(new_names)
+    }
+
+    fn placeholders(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|p| match p {
+            Part::Placeholder(name) => Some(name.as_str()),
+            Part::Text(_) => None,
+        })
+    }
+}
+
+fn is_builtin(name: &str) -> bool {
+    matches!(name, "stem" | "ext" | "parent") || parse_row_width(name).is_some()
+}
+
+// Accepts row or row:N, where N is the padded width.
+fn parse_row_width(name: &str) -> Option<usize> {
+    match name {
+        "row" => Some(0),
+        n => n.strip_prefix("row:").and_then(|w| w.parse().ok()),
+    }
+}
+
+fn get_value(name: &str, row: &Row) -> String {
+    if let Some(width) = parse_row_width(name) {
+        return format!("{:0width$}", row.counts, width = width);
+    }
+
+    match name {
+        "stem" => extension::split_file_names(row.old_names).0,
+        "ext" => extension::get_extension(row.old_names).trim_start_matches('.').to_string(),
+        "parent" => row.old_names.parent()
+            .and_then(|p| p.file_name())
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        name => parser::get_column_index(row.headers, name)
+            .and_then(|idx| row.recs.get(idx))
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn headers() -> StringRecord {
+        StringRecord::from(vec!["path", "species", "voucher", "read"])
+    }
+
+    #[test]
+    fn parse_template_test() {
+        let template = Template::parse("{species}_{{x}}.{ext}").unwrap();
+        assert_eq!(vec![
+            Part::Placeholder(String::from("species")),
+            Part::Text(String::from("_{x}.")),
+            Part::Placeholder(String::from("ext")),
+        ], template.parts);
+
+        assert!(Template::parse("{species").is_err());
+        assert!(Template::parse("species}").is_err());
+        assert!(Template::parse("{}_{ext}").is_err());
+        assert!(Template::parse("new_name").is_err());
+    }
+
+    #[test]
+    fn check_placeholders_test() {
+        let template = Template::parse("{species}_{4}_{row:3}.{ext}").unwrap();
+        assert!(template.check_placeholders(&headers()).is_ok());
+
+        let template = Template::parse("{genus}_{9}_{stem}").unwrap();
+        let err = template.check_placeholders(&headers()).unwrap_err();
+        assert!(matches!(err, Error::Table(ref e) if e.len() == 2));
+    }
+
+    #[test]
+    fn fill_template_test() {
+        let template = Template::parse("{species}_{voucher}_{read}_{row:3}_{parent}.{ext}").unwrap();
+        let recs = StringRecord::from(vec!["raw/a.fastq.gz", "Bunomys_andrewsi", "LSUMZ_123", "R1"]);
+        let row = Row {
+            headers: &headers(),
+            recs: &recs,
+            old_names: Path::new("raw/a.fastq.gz"),
+            counts: 7,
+            lcounts: 8,
+        };
+
+        assert_eq!("Bunomys_andrewsi_LSUMZ_123_R1_007_raw.fastq.gz", template.fill(&row).unwrap());
+    }
+
+    #[test]
+    fn fill_template_empty_err_test() {
+        let template = Template::parse("{species}_{voucher}.{ext}").unwrap();
+        let recs = StringRecord::from(vec!["a.fq", "Bunomys_andrewsi", ""]);
+        let row = Row {
+            headers: &headers(),
+            recs: &recs,
+            old_names: Path::new("a.fq"),
+            counts: 1,
+            lcounts: 2,
+        };
+
+        let err = template.fill(&row).unwrap_err();
+        assert_eq!("INVALID INPUT! EMPTY VALUE FOR {voucher} IN THE TEMPLATE IN LINE 2.", err.to_string());
+    }
+}
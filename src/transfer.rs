@@ -0,0 +1,236 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// How the files get their new names.
+// Copies and links leave the original files untouched.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Mode {
+    #[default]
+    Rename,
+    Copy,
+    Hardlink,
+    Symlink,
+}
+
+impl Mode {
+    pub fn parse(mode: &str) -> Result<Self, String> {
+        match mode {
+            "rename" => Ok(Mode::Rename),
+            "copy" => Ok(Mode::Copy),
+            "hardlink" => Ok(Mode::Hardlink),
+            "symlink" => Ok(Mode::Symlink),
+            m => Err(format!("Invalid mode: {}. Use rename, copy, hardlink, or symlink.", m)),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Mode::Rename => "rename",
+            Mode::Copy => "copy",
+            Mode::Hardlink => "hardlink",
+            Mode::Symlink => "symlink",
+        }
+    }
+
+    // For the summary. Total files renamed, copied, and so on.
+    pub fn past_tense(&self) -> &str {
+        match self {
+            Mode::Rename => "renamed",
+            Mode::Copy => "copied",
+            Mode::Hardlink => "hard linked",
+            Mode::Symlink => "symlinked",
+        }
+    }
+}
+
+// Gives the file its new name following the mode.
+// Copies are verified. Returns the checksum of copied files.
+pub fn transfer_file(mode: Mode, origin: &Path, destination: &Path) -> io::Result<Option<String>> {
+    match mode {
+        Mode::Rename => move_file(origin, destination),
+        Mode::Copy => copy_verified(origin, destination).map(Some),
+        Mode::Hardlink => fs::hard_link(origin, destination).map(|_| None),
+        Mode::Symlink => create_symlink(origin, destination).map(|_| None),
+    }
+}
+
+// Symlinks point to the absolute path of the original,
+// so they work wherever the link is.
+fn create_symlink(origin: &Path, destination: &Path) -> io::Result<()> {
+    let target = fs::canonicalize(origin)?;
+
+    #[cfg(unix)]
+    return std::os::unix::fs::symlink(target, destination);
+
+    #[cfg(windows)]
+    return std::os::windows::fs::symlink_file(target, destination);
+}
+
+// Renames the file. If the destination is on another filesystem,
+// the file is copied, verified, and only then the original is removed.
+// If the original can't be removed, the copy is removed instead,
+// so the file is left only under its old name.
+// Returns the sha256 checksum of the copied file,
+// or None if the file was simply renamed.
+pub fn move_file(origin: &Path, destination: &Path) -> io::Result<Option<String>> {
+    match fs::rename(origin, destination) {
+        Ok(()) => Ok(None),
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            let checksum = copy_verified(origin, destination)?;
+            if let Err(e) = fs::remove_file(origin) {
+                fs::remove_file(destination).ok();
+                return Err(e);
+            }
+            Ok(Some(checksum))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// The copy is written to a temporary file next to the destination,
+// so an interrupted copy never leaves a partial file under the new name.
+// The temporary file is read back and compared with the original
+// before it takes the new name.
+pub fn copy_verified(origin: &Path, destination: &Path) -> io::Result<String> {
+    let temp = create_temp_names(destination);
+    let copied = copy_with_checksum(origin, &temp)
+        .and_then(|(size, checksum)| {
+            let (copy_size, copy_checksum) = compute_checksum(&temp)?;
+            if size != copy_size || checksum != copy_checksum {
+                return Err(io::Error::new(ErrorKind::InvalidData, format!(
+                    "COPY OF {:?} DOESN'T MATCH THE ORIGINAL", origin
+                )));
+            }
+            fs::rename(&temp, destination)?;
+            Ok(checksum)
+        });
+
+    if copied.is_err() {
+        fs::remove_file(&temp).ok();
+    }
+
+    copied
+}
+
+fn create_temp_names(destination: &Path) -> PathBuf {
+    let fname = destination.file_name().unwrap_or_default().to_string_lossy();
+    destination.with_file_name(format!(".renamer-copy_{}", fname))
+}
+
+// Removes the temporary file of a copy that was interrupted.
+// Returns its path if there was one.
+pub fn remove_temp_copy(destination: &Path) -> io::Result<Option<PathBuf>> {
+    let temp = create_temp_names(destination);
+    match fs::remove_file(&temp) {
+        Ok(()) => Ok(Some(temp)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// Streams the file into the copy, hashing it on the way.
+// Permissions and modification time follow the original.
+fn copy_with_checksum(origin: &Path, copy: &Path) -> io::Result<(u64, String)> {
+    let source = File::open(origin)?;
+    let metadata = source.metadata()?;
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, source);
+    let mut target = File::create(copy)?;
+
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; BUFFER_SIZE];
+    let mut size = 0;
+    loop {
+        let bytes = reader.read(&mut buffer)?;
+        if bytes == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes]);
+        target.write_all(&buffer[..bytes])?;
+        size += bytes as u64;
+    }
+
+    target.set_permissions(metadata.permissions())?;
+    if let Ok(modified) = metadata.modified() {
+        target.set_modified(modified)?;
+    }
+    target.sync_all()?;
+
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+pub fn compute_checksum(path: &Path) -> io::Result<(u64, String)> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut file, &mut hasher)?;
+
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn parse_mode_test() {
+        assert_eq!(Ok(Mode::Copy), Mode::parse("copy"));
+        assert_eq!(Ok(Mode::Symlink), Mode::parse("symlink"));
+        assert!(Mode::parse("move").is_err());
+        ["rename", "copy", "hardlink", "symlink"].iter()
+            .for_each(|m| assert_eq!(*m, Mode::parse(m).unwrap().as_str()));
+    }
+
+    #[test]
+    fn transfer_file_links_test() {
+        let dir = env::temp_dir().join("renamer_transfer_links_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let origin = dir.join("a.fq");
+        fs::write(&origin, "ACGT").unwrap();
+
+        transfer_file(Mode::Hardlink, &origin, &dir.join("hard.fq")).unwrap();
+        transfer_file(Mode::Symlink, &origin, &dir.join("sym.fq")).unwrap();
+
+        assert!(origin.is_file());
+        assert_eq!("ACGT", fs::read_to_string(dir.join("hard.fq")).unwrap());
+        assert!(fs::symlink_metadata(dir.join("sym.fq")).unwrap().file_type().is_symlink());
+        assert_eq!("ACGT", fs::read_to_string(dir.join("sym.fq")).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn copy_verified_test() {
+        let dir = env::temp_dir().join("renamer_copy_verified_test");
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        let origin = dir.join("a.fq");
+        let destination = dir.join("b.fq");
+        fs::write(&origin, "@read1\nACGT\n+\nIIII\n").unwrap();
+
+        let checksum = copy_verified(&origin, &destination).unwrap();
+
+        assert_eq!(compute_checksum(&origin).unwrap().1, checksum);
+        assert_eq!(fs::read(&origin).unwrap(), fs::read(&destination).unwrap());
+        assert!(!create_temp_names(&destination).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compute_checksum_test() {
+        let dir = env::temp_dir().join("renamer_checksum_test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("empty.txt");
+        File::create(&path).unwrap();
+
+        assert_eq!(
+            (0, String::from("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")),
+            compute_checksum(&path).unwrap()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
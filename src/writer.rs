@@ -1,21 +1,52 @@
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
 use std::fs::File;
-use std::io::{LineWriter, Write, Result};
+use std::io::{self, LineWriter, Write};
+
+use crate::checksum::{self, Algorithm};
+use crate::error::Error;
+use crate::extension;
+use crate::output::Progress;
+
+// With a checksum, each file is hashed for the checksum column.
+pub fn write_to_csv(recs: &mut [PathBuf], bpa: bool, checksum: Option<Algorithm>) -> Result<(), Error> {
+    recs.sort();
+    let mut ids: Vec<Id> = recs.iter().map(|r| Id::new(r)).collect();
+    if let Some(algo) = checksum {
+        info!("Computing {} checksums...", algo.as_str());
+        let mut progress = Progress::new(recs.len());
+        for (id, path) in ids.iter_mut().zip(recs.iter()) {
+            progress.tick();
+            id.checksum = Some(checksum::compute_checksum(path, algo).map_err(Error::io(path))?);
+        }
+        progress.finish();
+    }
+    write_ids("renamer-finder.csv", &mut ids, bpa, checksum)
+}
+
+// The new names are filled with the proposed names,
+// so the csv can be reviewed and used as the rename input.
+pub fn write_names_to_csv(fname: &str, recs: &[(PathBuf, String)]) -> Result<(), Error> {
+    let mut ids: Vec<Id> = recs.iter()
+        .map(|(path, new_names)| {
+            let mut id = Id::new(path);
+            id.new_names = new_names.to_string();
+            id
+        })
+        .collect();
+    write_ids(fname, &mut ids, false, None)
+}
 
-pub fn write_to_csv(recs: &mut [PathBuf], bpa: bool) -> Result<()> {
-    let fname = "renamer-finder.csv";
-    let csv = File::create(&fname).unwrap();
+fn write_ids(fname: &str, ids: &mut [Id], bpa: bool, checksum: Option<Algorithm>) -> Result<(), Error> {
+    let csv = File::create(fname).map_err(Error::io(Path::new(fname)))?;
     let mut line = LineWriter::new(csv);
 
-    recs.sort_by(|a, b| a.cmp(&b));
-    write_header(&mut line, bpa);
-    recs.iter()
-        .for_each(|r| {
-            let mut id = Id::new(&r);
-            write_content(&mut id, &mut line, bpa);
-        });
+    write_header(&mut line, bpa, checksum).map_err(Error::io(Path::new(fname)))?;
+    ids.iter_mut()
+        .try_for_each(|id| write_content(id, &mut line, bpa))
+        .map_err(Error::io(Path::new(fname)))?;
 
-    println!("The result is saved as {}", &fname);
+    info!("The result is saved as {}", &fname);
     Ok(())
 }
 
@@ -27,18 +58,20 @@ struct Id {
     fstem: String,
     file_id: String,
     read_id: String,
+    checksum: Option<String>,
 }
 
 impl Id {
-    fn new(lines: &PathBuf) -> Self {
+    fn new(lines: &Path) -> Self {
         Self {
             full_path: lines.to_string_lossy().into_owned(),
             new_names: String::from("FILL HERE!"),
-            parent_path: lines.parent().unwrap().to_string_lossy().into_owned(),
-            fname: lines.file_name().unwrap().to_string_lossy().into_owned(),
-            fstem: lines.file_stem().unwrap().to_string_lossy().into_owned(),
+            parent_path: lines.parent().unwrap_or_else(|| Path::new("")).to_string_lossy().into_owned(),
+            fname: lines.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            fstem: extension::split_file_names(lines).0,
             file_id: String::from("N/A"), 
-            read_id: String::from("N/A")
+            read_id: String::from("N/A"),
+            checksum: None,
         }
     }
 
@@ -52,34 +85,52 @@ impl Id {
     }
 }
 
-fn write_header<W: Write>(line:&mut W, bpa: bool) {
-    write!(line, "full_path,new_names,parent_path,filenames,file_stem").unwrap();
+fn write_header<W: Write>(line:&mut W, bpa: bool, checksum: Option<Algorithm>) -> io::Result<()> {
+    write!(line, "full_path,new_names,parent_path,filenames,file_stem")?;
 
     if bpa {
-        write!(line, ",id,read_id").unwrap();
+        write!(line, ",id,read_id")?;
     } 
+
+    if let Some(algo) = checksum {
+        write!(line, ",{}", algo.as_str())?;
+    }
     
-    writeln!(line).unwrap();
+    writeln!(line)
 }
 
-fn write_content<W: Write>(id: &mut Id, line:&mut W, bpa: bool) {
+fn write_content<W: Write>(id: &mut Id, line:&mut W, bpa: bool) -> io::Result<()> {
     write!(line, "{},{},{},{},{}", 
-        id.full_path, 
-        id.new_names,
-        id.parent_path, 
-        id.fname,
-        id.fstem
-    ).unwrap();
+        quote(&id.full_path),
+        quote(&id.new_names),
+        quote(&id.parent_path),
+        quote(&id.fname),
+        quote(&id.fstem)
+    )?;
 
     if bpa {
         id.split_file_names();
         write!(line, ",{},{}", 
             id.file_id, 
             id.read_id
-        ).unwrap();
+        )?;
     } 
+
+    if let Some(checksum) = &id.checksum {
+        write!(line, ",{}", checksum)?;
+    }
     
-    writeln!(line).unwrap();          
+    writeln!(line)
+}
+
+// File names may contain commas and quotes.
+// Such fields are quoted, so the csv reads back as written.
+fn quote(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
 }
 
 #[cfg(test)]
@@ -97,6 +148,13 @@ mod test {
         assert_eq!("N/A", id.read_id);
     }
 
+    #[test]
+    fn quote_test() {
+        assert_eq!("sample.fq", quote("sample.fq"));
+        assert_eq!("\"a, b.fq\"", quote("a, b.fq"));
+        assert_eq!("\"a \"\"b\"\".fq\"", quote("a \"b\".fq"));
+    }
+
     #[test]
     // Test for OMG database filenames. 
     fn path_modify_id_test() {
@@ -107,6 +165,7 @@ mod test {
         assert_eq!("data/26535_HDWND_AAGT_A1_L1_R2_01.fastq.gz", id.full_path);
         assert_eq!("FILL HERE!", id.new_names);
         assert_eq!("26535_HDWND_AAGT_A1_L1_R2_01.fastq.gz", id.fname);
+        assert_eq!("26535_HDWND_AAGT_A1_L1_R2_01", id.fstem);
         assert_eq!("26535_HDWND_AAGT", id.file_id);
         assert_eq!("A1_L1_R2_01.fastq.gz", id.read_id);
     }